@@ -1,54 +1,560 @@
 extern crate proc_macro;
 
 use proc_macro::TokenStream;
-use venial::{parse_declaration, Declaration, AttributeValue};
-use quote::quote;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote, quote_spanned};
+use venial::{parse_declaration, Attribute, AttributeValue, Declaration, NamedField, StructFields};
 
-#[proc_macro_derive(Prototype, attributes(prot_category))]
+/// Type the derive is applied to, either a struct with named fields or an enum with named fields
+/// in every variant.
+struct Target {
+    name: Ident,
+    attributes: Vec<Attribute>,
+    generic_params: TokenStream2,
+    generic_args: TokenStream2,
+    where_clause: TokenStream2,
+    shape: Shape,
+}
+
+enum Shape {
+    Struct(Vec<NamedField>),
+    Enum(Vec<(Ident, Vec<NamedField>)>),
+}
+
+/// Implements `Prototype` and registers the category with `inventory`, unless the type is generic.
+/// `#[prot_category(name)]` names the category in prototype files, `#[prot_name(field)]` picks the
+/// field holding the name if it isn't `name`. In prototype files the name is always under the
+/// `name` key, so such a field has to be renamed for serde as well. Enums need the name field in
+/// every variant. References are collected from every field whose type mentions `PrototypeRef`,
+/// the type of such a field has to implement `References`.
+#[proc_macro_derive(Prototype, attributes(prot_category, prot_name))]
 pub fn prototype_derive(input: TokenStream) -> TokenStream {
-    let declaration = parse_declaration(input.into()).unwrap();
-    if let Declaration::Struct(struct_decl) = declaration {
-        let struct_name = struct_decl.name;
-        let prot_table_category = struct_decl.attributes.iter().find_map(|attr| {
-            if attr.get_single_path_segment()? == "prot_category" {
-                if let AttributeValue::Group(_, toks) = &attr.value {
-                    Some(toks)
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        }).unwrap();
-        quote! {
-            impl Prototype<'_> for #struct_name {
-                fn name(&self) -> &str {
-                    &self.name
+    expand(input, prototype_impl)
+}
+
+/// Implements `ComponentPrototype`. By default the prototype is its own component, fields marked
+/// with `#[serde(skip)]` or `#[serde(skip_deserializing)]` are state and are kept when a reloaded
+/// prototype is applied.
+///
+/// With `#[prot_component(Type)]` the component is a separate struct. Fields of the prototype are
+/// cloned into the component fields with the same name, `#[prot_field(rename = field)]` picks
+/// another component field and `#[prot_field(skip)]` leaves the field out. Component fields that
+/// aren't in the prototype are either state starting from `Default::default()`, listed in
+/// `#[prot_default(field, ...)]`, or computed from the prototype by a function listed in
+/// `#[prot_computed(field = function, ...)]`. State is kept when a reloaded prototype is
+/// applied, computed fields are computed again.
+#[proc_macro_derive(
+    ComponentPrototype,
+    attributes(prot_component, prot_default, prot_computed, prot_field)
+)]
+pub fn component_prototype_derive(input: TokenStream) -> TokenStream {
+    expand(input, component_prototype_impl)
+}
+
+fn expand(
+    input: TokenStream,
+    generate: fn(Target) -> Result<TokenStream2, TokenStream2>,
+) -> TokenStream {
+    let declaration = match parse_declaration(input.into()) {
+        Ok(declaration) => declaration,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    match target(declaration).and_then(generate) {
+        Ok(tokens) | Err(tokens) => tokens.into(),
+    }
+}
+
+/// Diagnostic pointing at `span`, emitted instead of the generated code.
+fn error(span: Span, message: &str) -> TokenStream2 {
+    quote_spanned! {span=> compile_error!(#message); }
+}
+
+fn target(declaration: Declaration) -> Result<Target, TokenStream2> {
+    match declaration {
+        Declaration::Struct(struct_decl) => {
+            let fields = match &struct_decl.fields {
+                StructFields::Named(fields) => fields
+                    .fields
+                    .iter()
+                    .map(|(field, _)| field.clone())
+                    .collect(),
+                _ => {
+                    return Err(error(
+                        struct_decl.name.span(),
+                        "prototypes have to be structs with named fields or enums",
+                    ))
                 }
+            };
+            let generic_args = struct_decl.get_inline_generic_args();
+            let generic_args = quote!(#generic_args);
+            let generic_params = &struct_decl.generic_params;
+            let where_clause = &struct_decl.where_clause;
+            Ok(Target {
+                generic_params: quote!(#generic_params),
+                generic_args,
+                where_clause: quote!(#where_clause),
+                name: struct_decl.name,
+                attributes: struct_decl.attributes,
+                shape: Shape::Struct(fields),
+            })
+        }
+        Declaration::Enum(enum_decl) => {
+            let mut variants = Vec::new();
+            for (variant, _) in enum_decl.variants.iter() {
+                match &variant.contents {
+                    StructFields::Named(fields) => variants.push((
+                        variant.name.clone(),
+                        fields
+                            .fields
+                            .iter()
+                            .map(|(field, _)| field.clone())
+                            .collect(),
+                    )),
+                    _ => {
+                        return Err(error(
+                            variant.name.span(),
+                            "every variant of an enum prototype has to have named fields",
+                        ))
+                    }
+                }
+            }
+            if variants.is_empty() {
+                return Err(error(
+                    enum_decl.name.span(),
+                    "enum prototypes need at least one variant",
+                ));
+            }
+            let generic_args = enum_decl.get_inline_generic_args();
+            let generic_args = quote!(#generic_args);
+            let generic_params = &enum_decl.generic_params;
+            let where_clause = &enum_decl.where_clause;
+            Ok(Target {
+                generic_params: quote!(#generic_params),
+                generic_args,
+                where_clause: quote!(#where_clause),
+                name: enum_decl.name,
+                attributes: enum_decl.attributes,
+                shape: Shape::Enum(variants),
+            })
+        }
+        _ => Err(error(
+            Span::call_site(),
+            "prototypes have to be structs with named fields or enums",
+        )),
+    }
+}
 
-                fn from_pt<'a, 'b>(prototypes_table: &'a Prototypes, name: &'b str) -> Option<&'a Self> {
-                    prototypes_table.#(#prot_table_category)*.get(name)
+fn find_attribute<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+    attributes.iter().find(|attr| {
+        attr.get_single_path_segment()
+            .map_or(false, |segment| segment == name)
+    })
+}
+
+/// Argument of an attribute in the form of `#[attribute(ident)]`.
+fn single_ident(attr: &Attribute, message: &str) -> Result<Ident, TokenStream2> {
+    match &attr.value {
+        AttributeValue::Group(_, toks) => match toks.as_slice() {
+            [TokenTree::Ident(ident)] => Ok(ident.clone()),
+            [first, ..] => Err(error(first.span(), message)),
+            [] => Err(error(attr.path[0].span(), message)),
+        },
+        _ => Err(error(attr.path[0].span(), message)),
+    }
+}
+
+/// Arguments of an attribute in the form of `#[attribute(arg, ...)]`.
+fn attribute_args(attr: &Attribute) -> Vec<&[TokenTree]> {
+    match &attr.value {
+        AttributeValue::Group(_, toks) => toks
+            .split(|tok| matches!(tok, TokenTree::Punct(punct) if punct.as_char() == ','))
+            .filter(|arg| !arg.is_empty())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn has_field(fields: &[NamedField], name: &Ident) -> bool {
+    fields.iter().any(|field| field.name == *name)
+}
+
+fn name_field(attributes: &[Attribute]) -> Result<Ident, TokenStream2> {
+    match find_attribute(attributes, "prot_name") {
+        Some(attr) => single_ident(
+            attr,
+            "expected the field holding the name: `#[prot_name(field)]`",
+        ),
+        None => Ok(Ident::new("name", Span::call_site())),
+    }
+}
+
+fn prototype_impl(target: Target) -> Result<TokenStream2, TokenStream2> {
+    let Target {
+        name: struct_name,
+        generic_params,
+        generic_args,
+        where_clause,
+        ..
+    } = &target;
+    let category =
+        match find_attribute(&target.attributes, "prot_category") {
+            Some(attr) => single_ident(
+                attr,
+                "expected the name of the category: `#[prot_category(name)]`",
+            )?,
+            None => return Err(error(
+                struct_name.span(),
+                "missing `#[prot_category(name)]` attribute naming the category of the prototypes",
+            )),
+        };
+    let category = category.to_string();
+    let name_attr = find_attribute(&target.attributes, "prot_name");
+    let name_field = name_field(&target.attributes)?;
+    let missing_name = |span: Span| {
+        let message = match name_attr {
+            Some(_) => format!("no field `{}` to take the name from", name_field),
+            None => {
+                "missing `name` field, use `#[prot_name(field)]` to pick another one".to_string()
+            }
+        };
+        error(name_attr.map_or(span, |_| name_field.span()), &message)
+    };
+    let name = match &target.shape {
+        Shape::Struct(fields) => {
+            if !has_field(fields, &name_field) {
+                return Err(missing_name(struct_name.span()));
+            }
+            quote!(&self.#name_field)
+        }
+        Shape::Enum(variants) => {
+            let mut arms = Vec::new();
+            for (variant, fields) in variants {
+                if !has_field(fields, &name_field) {
+                    return Err(missing_name(variant.span()));
                 }
+                arms.push(quote!(Self::#variant { #name_field, .. } => #name_field));
+            }
+            quote!(match self { #(#arms,)* })
+        }
+    };
+    let references = references(&target.shape);
+    // Categories can only be registered for concrete types
+    let register = if generic_params.is_empty() {
+        quote! {
+            inventory::submit! {
+                PrototypeCategory::new::<#struct_name>()
             }
-        }.into()
+        }
     } else {
-        quote!{}.into()
+        quote!()
+    };
+    Ok(quote! {
+        impl #generic_params Prototype<'_> for #struct_name #generic_args #where_clause {
+            fn category() -> &'static str {
+                #category
+            }
+
+            fn name(&self) -> &str {
+                #name
+            }
+
+            #references
+        }
+
+        #register
+    })
+}
+
+fn component_prototype_impl(target: Target) -> Result<TokenStream2, TokenStream2> {
+    if let Some(attr) = find_attribute(&target.attributes, "prot_component") {
+        return separate_component_impl(&target, attr);
+    }
+    for attr_name in ["prot_default", "prot_computed"] {
+        if let Some(attr) = find_attribute(&target.attributes, attr_name) {
+            return Err(error(
+                attr.path[0].span(),
+                "only components separate from the prototype have fields of their own, \
+                 add `#[prot_component(Type)]`",
+            ));
+        }
     }
+    let Target {
+        name: struct_name,
+        generic_params,
+        generic_args,
+        where_clause,
+        ..
+    } = &target;
+    let apply = match &target.shape {
+        Shape::Struct(fields) => {
+            let prototype_fields = prototype_fields(fields);
+            quote! {
+                #(component.#prototype_fields = self.#prototype_fields.clone();)*
+            }
+        }
+        Shape::Enum(variants) => {
+            // Switching to another variant replaces the state as well
+            let arms = variants.iter().map(|(variant, fields)| {
+                let prototype_fields = prototype_fields(fields);
+                let values: Vec<_> = prototype_fields.iter()
+                    .map(|field| format_ident!("prototype_{}", field))
+                    .collect();
+                quote! {
+                    (Self::#variant { #(#prototype_fields: #values,)* .. }, Self::#variant { #(#prototype_fields,)* .. }) => {
+                        #(*#prototype_fields = #values.clone();)*
+                    }
+                }
+            });
+            quote! {
+                #[allow(unreachable_patterns)]
+                match (self, component) {
+                    #(#arms)*
+                    (prototype, component) => *component = prototype.clone(),
+                }
+            }
+        }
+    };
+    Ok(quote! {
+        impl #generic_params ComponentPrototype<'_> for #struct_name #generic_args #where_clause {
+            fn to_component(&self) -> Self {
+                self.clone()
+            }
+
+            fn apply_to(&self, component: &mut Self) {
+                #apply
+            }
+
+            fn prototype_name(component: &Self) -> &str {
+                Prototype::name(component)
+            }
+        }
+    })
 }
 
-#[proc_macro_derive(ComponentPrototype)]
-pub fn component_prototype_derive(input: TokenStream) -> TokenStream {
-    let declaration = parse_declaration(input.into()).unwrap();
-    if let Declaration::Struct(struct_decl) = declaration {
-        let struct_name = struct_decl.name;
-        quote! {
-            impl ComponentPrototype<'_> for #struct_name {
-                fn to_component(&self) -> Self {
-                    self.clone()
+/// `ComponentPrototype` converting the prototype into the component type given by
+/// `#[prot_component(Type)]`.
+fn separate_component_impl(
+    target: &Target,
+    component_attr: &Attribute,
+) -> Result<TokenStream2, TokenStream2> {
+    let Target {
+        name: struct_name,
+        generic_params,
+        generic_args,
+        where_clause,
+        ..
+    } = target;
+    let component: TokenStream2 = match &component_attr.value {
+        AttributeValue::Group(_, toks) if !toks.is_empty() => toks.iter().cloned().collect(),
+        _ => {
+            return Err(error(
+                component_attr.path[0].span(),
+                "expected the component type: `#[prot_component(Type)]`",
+            ))
+        }
+    };
+    let fields = match &target.shape {
+        Shape::Struct(fields) => fields,
+        Shape::Enum(_) => {
+            return Err(error(
+                component_attr.path[0].span(),
+                "only struct prototypes can be converted into a separate component",
+            ))
+        }
+    };
+    let name_field = name_field(&target.attributes)?;
+    // (component field, prototype field)
+    let mut copied = Vec::new();
+    let mut component_name = None;
+    for field in fields {
+        if let Some(component_field) = component_field(field)? {
+            if field.name == name_field {
+                component_name = Some(component_field.clone());
+            }
+            copied.push((component_field, field.name.clone()));
+        }
+    }
+    let component_name = match component_name {
+        Some(component_name) => component_name,
+        None => {
+            let span = fields
+                .iter()
+                .find(|field| field.name == name_field)
+                .map_or(struct_name.span(), |field| field.name.span());
+            return Err(error(
+                span,
+                "the component has to keep the name of its prototype to be updated when the \
+                 prototype is reloaded",
+            ));
+        }
+    };
+    let mut defaulted = Vec::new();
+    if let Some(attr) = find_attribute(&target.attributes, "prot_default") {
+        for arg in attribute_args(attr) {
+            match arg {
+                [TokenTree::Ident(field)] => defaulted.push(field.clone()),
+                [first, ..] => return Err(error(first.span(), "expected a component field")),
+                [] => {}
+            }
+        }
+    }
+    let mut computed_fields = Vec::new();
+    let mut computed_values = Vec::new();
+    if let Some(attr) = find_attribute(&target.attributes, "prot_computed") {
+        for arg in attribute_args(attr) {
+            match arg {
+                [TokenTree::Ident(field), TokenTree::Punct(eq), function @ ..]
+                    if eq.as_char() == '=' && !function.is_empty() =>
+                {
+                    let function: TokenStream2 = function.iter().cloned().collect();
+                    computed_fields.push(field.clone());
+                    computed_values.push(quote!((#function)(self)));
                 }
+                [first, ..] => {
+                    return Err(error(
+                        first.span(),
+                        "expected a component field computed by a function: `field = function`",
+                    ))
+                }
+                [] => {}
             }
-        }.into()
-    } else {
-        quote!{}.into()
+        }
     }
+    let (component_fields, prototype_fields): (Vec<_>, Vec<_>) = copied.into_iter().unzip();
+    Ok(quote! {
+        impl #generic_params ComponentPrototype<'_, #component>
+            for #struct_name #generic_args #where_clause
+        {
+            fn to_component(&self) -> #component {
+                #component {
+                    #(#component_fields: self.#prototype_fields.clone(),)*
+                    #(#computed_fields: #computed_values,)*
+                    #(#defaulted: ::core::default::Default::default(),)*
+                }
+            }
+
+            fn apply_to(&self, component: &mut #component) {
+                #(component.#component_fields = self.#prototype_fields.clone();)*
+                #(component.#computed_fields = #computed_values;)*
+            }
+
+            fn prototype_name(component: &#component) -> &str {
+                &component.#component_name
+            }
+        }
+    })
+}
+
+/// Component field a prototype field is cloned into, `None` if it's skipped.
+fn component_field(field: &NamedField) -> Result<Option<Ident>, TokenStream2> {
+    let mut component_field = Some(field.name.clone());
+    for attr in &field.attributes {
+        if attr
+            .get_single_path_segment()
+            .map_or(true, |segment| segment != "prot_field")
+        {
+            continue;
+        }
+        for arg in attribute_args(attr) {
+            match arg {
+                [TokenTree::Ident(skip)] if skip == "skip" => component_field = None,
+                [TokenTree::Ident(rename), TokenTree::Punct(eq), TokenTree::Ident(renamed)]
+                    if rename == "rename" && eq.as_char() == '=' =>
+                {
+                    component_field = Some(renamed.clone())
+                }
+                [first, ..] => {
+                    return Err(error(first.span(), "expected `skip` or `rename = field`"))
+                }
+                [] => {}
+            }
+        }
+    }
+    Ok(component_field)
+}
+
+/// `references` method collecting references from fields that can hold them, nothing if there
+/// are no such fields.
+fn references(shape: &Shape) -> TokenStream2 {
+    let collect = |fields: &[&NamedField], receiver: TokenStream2| -> TokenStream2 {
+        let collected = fields.iter().map(|field| {
+            let name = &field.name;
+            let key = name.to_string();
+            let key = key.trim_start_matches("r#");
+            quote!(References::collect(#receiver #name, #key, &mut references);)
+        });
+        quote!(#(#collected)*)
+    };
+    let body = match shape {
+        Shape::Struct(fields) => {
+            let fields = reference_fields(fields);
+            if fields.is_empty() {
+                return quote!();
+            }
+            collect(&fields, quote!(&self.))
+        }
+        Shape::Enum(variants) => {
+            if variants
+                .iter()
+                .all(|(_, fields)| reference_fields(fields).is_empty())
+            {
+                return quote!();
+            }
+            let arms = variants.iter().map(|(variant, fields)| {
+                let fields = reference_fields(fields);
+                let names = fields.iter().map(|field| &field.name);
+                let collected = collect(&fields, quote!());
+                quote!(Self::#variant { #(#names,)* .. } => { #collected })
+            });
+            quote!(match self { #(#arms)* })
+        }
+    };
+    quote! {
+        fn references(&self) -> Vec<Reference<'_>> {
+            let mut references = Vec::new();
+            #body
+            references
+        }
+    }
+}
+
+fn reference_fields(fields: &[NamedField]) -> Vec<&NamedField> {
+    fields
+        .iter()
+        .filter(|field| field.ty.tokens.iter().any(mentions_prototype_ref))
+        .collect()
+}
+
+fn mentions_prototype_ref(tok: &TokenTree) -> bool {
+    match tok {
+        TokenTree::Ident(ident) => ident == "PrototypeRef",
+        TokenTree::Group(group) => group
+            .stream()
+            .into_iter()
+            .any(|tok| mentions_prototype_ref(&tok)),
+        _ => false,
+    }
+}
+
+/// Fields holding values from the prototype, not state.
+fn prototype_fields(fields: &[NamedField]) -> Vec<Ident> {
+    fields
+        .iter()
+        .filter(|field| !is_serde_skipped(field))
+        .map(|field| field.name.clone())
+        .collect()
+}
+
+/// Fields that aren't deserialized, marked with `#[serde(skip)]` or
+/// `#[serde(skip_deserializing)]`, hold state instead of values from the prototype.
+fn is_serde_skipped(field: &NamedField) -> bool {
+    field.attributes.iter().any(|attr| {
+        attr.get_single_path_segment()
+            .map_or(false, |segment| segment == "serde")
+            && matches!(&attr.value, AttributeValue::Group(_, toks) if toks.iter().any(is_skip))
+    })
+}
+
+fn is_skip(tok: &TokenTree) -> bool {
+    matches!(tok, TokenTree::Ident(ident) if ident == "skip" || ident == "skip_deserializing")
 }
@@ -0,0 +1,11 @@
+use scriplets_derive::ComponentPrototype;
+
+#[derive(ComponentPrototype)]
+#[prot_component(Movement)]
+struct MovementPrototype {
+    #[prot_field(skip)]
+    name: String,
+    speed: f32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use scriplets_derive::ComponentPrototype;
+
+#[derive(Clone, ComponentPrototype)]
+#[prot_default(input_move)]
+struct Movement {
+    name: String,
+    input_move: f32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use scriplets_derive::Prototype;
+
+#[derive(Prototype)]
+#[prot_category(movement)]
+enum Movement {
+    Tank { name: String, speed: f32 },
+    Hover { speed: f32 },
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use scriplets_derive::Prototype;
+
+#[derive(Prototype)]
+struct Radio {
+    name: String,
+}
+
+fn main() {}
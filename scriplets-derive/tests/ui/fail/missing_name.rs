@@ -0,0 +1,9 @@
+use scriplets_derive::Prototype;
+
+#[derive(Prototype)]
+#[prot_category(radio)]
+struct Radio {
+    range: f32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use scriplets_derive::Prototype;
+
+#[derive(Prototype)]
+#[prot_category(radio)]
+#[prot_name(id)]
+struct Radio {
+    name: String,
+}
+
+fn main() {}
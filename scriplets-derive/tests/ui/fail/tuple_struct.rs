@@ -0,0 +1,7 @@
+use scriplets_derive::Prototype;
+
+#[derive(Prototype)]
+#[prot_category(radio)]
+struct Radio(String);
+
+fn main() {}
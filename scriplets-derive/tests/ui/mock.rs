@@ -0,0 +1,86 @@
+//! Stand-ins for the traits and the prototypes table of the game crate, which the generated code
+//! refers to.
+
+#![allow(dead_code)]
+
+pub trait Prototype<'de> {
+    fn category() -> &'static str;
+    fn name(&self) -> &str;
+    fn references(&self) -> Vec<Reference<'_>> {
+        Vec::new()
+    }
+    fn from_pt<'a, 'b>(prototypes_table: &'a Prototypes, name: &'b str) -> Option<&'a Self>
+    where
+        Self: Sized + 'static,
+    {
+        prototypes_table.get(name)
+    }
+}
+
+pub trait ComponentPrototype<'de, T = Self>: Prototype<'de> {
+    fn to_component(&self) -> T;
+    fn apply_to(&self, component: &mut T);
+    fn prototype_name(component: &T) -> &str;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Reference<'a> {
+    pub field: &'static str,
+    pub category: &'static str,
+    pub name: &'a str,
+}
+
+pub trait References {
+    fn collect<'a>(&'a self, field: &'static str, references: &mut Vec<Reference<'a>>);
+}
+
+pub struct PrototypeRef<T> {
+    pub name: String,
+    pub prototype: std::marker::PhantomData<T>,
+}
+
+impl<T: for<'de> Prototype<'de>> References for PrototypeRef<T> {
+    fn collect<'a>(&'a self, field: &'static str, references: &mut Vec<Reference<'a>>) {
+        references.push(Reference {
+            field,
+            category: T::category(),
+            name: &self.name,
+        })
+    }
+}
+
+impl<R: References> References for Option<R> {
+    fn collect<'a>(&'a self, field: &'static str, references: &mut Vec<Reference<'a>>) {
+        if let Some(reference) = self {
+            reference.collect(field, references)
+        }
+    }
+}
+
+pub struct PrototypeCategory {
+    pub name: fn() -> &'static str,
+}
+
+inventory::collect!(PrototypeCategory);
+
+impl PrototypeCategory {
+    pub const fn new<P: for<'de> Prototype<'de>>() -> Self {
+        Self { name: P::category }
+    }
+
+    pub fn registered() -> Vec<&'static str> {
+        inventory::iter::<Self>
+            .into_iter()
+            .map(|category| (category.name)())
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct Prototypes;
+
+impl Prototypes {
+    pub fn get<P>(&self, _name: &str) -> Option<&P> {
+        None
+    }
+}
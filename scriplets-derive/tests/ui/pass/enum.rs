@@ -0,0 +1,47 @@
+#[path = "../mock.rs"]
+mod mock;
+
+use mock::*;
+use scriplets_derive::{ComponentPrototype, Prototype};
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Prototype, ComponentPrototype)]
+#[prot_category(movement)]
+enum Movement {
+    Tank {
+        name: String,
+        speed: f32,
+    },
+    Accelerated {
+        name: String,
+        acceleration: f32,
+        #[serde(skip)]
+        speed: f32,
+    },
+}
+
+fn main() {
+    let tank = Movement::Tank {
+        name: "tank".to_string(),
+        speed: 1.0,
+    };
+    let accelerated = Movement::Accelerated {
+        name: "accelerated".to_string(),
+        acceleration: 1.0,
+        speed: 0.0,
+    };
+    assert_eq!(tank.name(), "tank");
+    assert_eq!(accelerated.name(), "accelerated");
+    assert_eq!(Movement::category(), "movement");
+
+    let mut component = Movement::Accelerated {
+        name: "accelerated".to_string(),
+        acceleration: 0.5,
+        speed: 3.0,
+    };
+    accelerated.apply_to(&mut component);
+    assert!(matches!(component, Movement::Accelerated { acceleration, speed, .. }
+        if acceleration == 1.0 && speed == 3.0));
+    tank.apply_to(&mut component);
+    assert!(matches!(component, Movement::Tank { .. }));
+}
@@ -0,0 +1,33 @@
+#[path = "../mock.rs"]
+mod mock;
+
+use mock::*;
+use scriplets_derive::{ComponentPrototype, Prototype};
+
+#[derive(Clone, Prototype, ComponentPrototype)]
+#[prot_category(tagged)]
+struct Tagged<T>
+where
+    T: Clone,
+{
+    name: String,
+    value: T,
+}
+
+fn main() {
+    let prototype = Tagged {
+        name: "tagged".to_string(),
+        value: 1u8,
+    };
+    let mut component = Tagged {
+        name: "tagged".to_string(),
+        value: 0u8,
+    };
+    prototype.apply_to(&mut component);
+    assert_eq!(component.value, 1);
+    assert_eq!(component.name(), "tagged");
+    assert_eq!(Tagged::<u8>::category(), "tagged");
+    assert!(Tagged::<u8>::from_pt(&Prototypes::default(), "tagged").is_none());
+    // Generic prototypes aren't registered
+    assert!(PrototypeCategory::registered().is_empty());
+}
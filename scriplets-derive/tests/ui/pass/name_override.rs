@@ -0,0 +1,22 @@
+#[path = "../mock.rs"]
+mod mock;
+
+use mock::*;
+use scriplets_derive::Prototype;
+
+#[derive(Clone, Prototype)]
+#[prot_category(radio)]
+#[prot_name(id)]
+struct Radio {
+    id: String,
+    range: f32,
+}
+
+fn main() {
+    let prototype = Radio {
+        id: "default".to_string(),
+        range: 10.0,
+    };
+    assert_eq!(prototype.name(), "default");
+    assert_eq!(prototype.range, 10.0);
+}
@@ -0,0 +1,74 @@
+#[path = "../mock.rs"]
+mod mock;
+
+use mock::*;
+use scriplets_derive::Prototype;
+use serde::Deserialize;
+use std::marker::PhantomData;
+
+#[derive(Deserialize, Prototype)]
+#[prot_category(item)]
+struct Item {
+    name: String,
+}
+
+#[derive(Prototype)]
+#[prot_category(unit)]
+struct Unit {
+    name: String,
+    cargo: PrototypeRef<Item>,
+    spare: Option<PrototypeRef<Item>>,
+    health: f32,
+}
+
+#[derive(Prototype)]
+#[prot_category(turret)]
+enum Turret {
+    Loaded { name: String, ammo: PrototypeRef<Item> },
+    Empty { name: String },
+}
+
+fn reference(name: &str) -> PrototypeRef<Item> {
+    PrototypeRef {
+        name: name.to_string(),
+        prototype: PhantomData,
+    }
+}
+
+fn main() {
+    let unit = Unit {
+        name: "scout".to_string(),
+        cargo: reference("plate"),
+        spare: None,
+        health: 1.0,
+    };
+    assert_eq!(
+        unit.references(),
+        [Reference {
+            field: "cargo",
+            category: "item",
+            name: "plate",
+        }]
+    );
+
+    let loaded = Turret::Loaded {
+        name: "loaded".to_string(),
+        ammo: reference("shell"),
+    };
+    assert_eq!(
+        loaded.references(),
+        [Reference {
+            field: "ammo",
+            category: "item",
+            name: "shell",
+        }]
+    );
+    let empty = Turret::Empty {
+        name: "empty".to_string(),
+    };
+    assert!(empty.references().is_empty());
+    let item = Item {
+        name: "plate".to_string(),
+    };
+    assert!(item.references().is_empty());
+}
@@ -0,0 +1,54 @@
+#[path = "../mock.rs"]
+mod mock;
+
+use mock::*;
+use scriplets_derive::{ComponentPrototype, Prototype};
+
+#[derive(Prototype, ComponentPrototype)]
+#[prot_category(movement)]
+#[prot_component(Movement)]
+#[prot_default(input_move)]
+#[prot_computed(speed_per_tick = speed_per_tick)]
+struct MovementPrototype {
+    name: String,
+    #[prot_field(rename = max_speed)]
+    speed: f32,
+    #[prot_field(skip)]
+    description: String,
+}
+
+struct Movement {
+    name: String,
+    max_speed: f32,
+    speed_per_tick: f32,
+    input_move: f32,
+}
+
+fn speed_per_tick(prototype: &MovementPrototype) -> f32 {
+    prototype.speed / 60.0
+}
+
+fn main() {
+    let prototype = MovementPrototype {
+        name: "default".to_string(),
+        speed: 60.0,
+        description: "moves".to_string(),
+    };
+    let mut component = prototype.to_component();
+    assert_eq!(component.max_speed, 60.0);
+    assert_eq!(component.speed_per_tick, 1.0);
+    assert_eq!(component.input_move, 0.0);
+    assert_eq!(prototype.description, "moves");
+
+    component.input_move = 1.0;
+    let reloaded = MovementPrototype {
+        speed: 120.0,
+        ..prototype
+    };
+    reloaded.apply_to(&mut component);
+    assert_eq!(component.max_speed, 120.0);
+    assert_eq!(component.speed_per_tick, 2.0);
+    assert_eq!(component.input_move, 1.0);
+    assert_eq!(MovementPrototype::prototype_name(&component), "default");
+    assert_eq!(component.name, "default");
+}
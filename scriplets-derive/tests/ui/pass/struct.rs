@@ -0,0 +1,37 @@
+#[path = "../mock.rs"]
+mod mock;
+
+use mock::*;
+use scriplets_derive::{ComponentPrototype, Prototype};
+use serde::Deserialize;
+
+#[derive(Clone, Deserialize, Prototype, ComponentPrototype)]
+#[prot_category(radio)]
+struct Radio {
+    name: String,
+    range: f32,
+    #[serde(skip)]
+    received: Vec<String>,
+    #[serde(skip_deserializing)]
+    sent: u32,
+}
+
+fn main() {
+    let prototype = Radio {
+        name: "default".to_string(),
+        range: 10.0,
+        received: Vec::new(),
+        sent: 0,
+    };
+    let mut component = prototype.to_component();
+    component.received.push("hello".to_string());
+    component.sent = 1;
+    Radio { range: 20.0, ..prototype.clone() }.apply_to(&mut component);
+    assert_eq!(component.range, 20.0);
+    assert_eq!(component.received.len(), 1);
+    assert_eq!(component.sent, 1);
+    assert_eq!(component.name(), "default");
+    assert_eq!(Radio::category(), "radio");
+    assert!(Radio::from_pt(&Prototypes::default(), "default").is_none());
+    assert_eq!(PrototypeCategory::registered(), ["radio"]);
+}
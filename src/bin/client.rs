@@ -1,19 +1,58 @@
-use bevy::{
-    asset::LoadState,
-    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
-    prelude::*,
-    render::camera::ScalingMode,
-    time::Stopwatch,
-    window::PresentMode,
+use bevy::prelude::*;
+use scriplets::{
+    handshake::{client_handshake, DEFAULT_PORT},
+    prototypes::{read_pack, Prototypes},
 };
-use bevy_rapier2d::prelude::*;
-
-const CLEAR_COLOR: Color = Color::rgb(0.1, 0.1, 0.1);
-const RESOLUTION: f32 = 16.0 / 9.0;
+use std::{net::TcpStream, path::Path, process::ExitCode};
 
 pub struct UnitSprite(pub Handle<Image>);
 pub struct WallSprite(pub Handle<Image>);
 
-fn main() {
+/// Prototypes of the client itself, `None` if they can't be loaded.
+fn load_local_prototypes() -> Option<Prototypes> {
+    let fragments = match read_pack(Path::new("assets/prototypes")) {
+        Ok(fragments) => fragments,
+        Err(e) => {
+            eprintln!("Failed to load local prototypes: {}", e);
+            return None;
+        }
+    };
+    match Prototypes::from_packs(&[fragments.iter().collect()]) {
+        Ok((prototypes, _)) => Some(prototypes),
+        Err(e) => {
+            eprintln!("Failed to load local prototypes: {}", e);
+            None
+        }
+    }
+}
+
+/// Usage: `client [address] [--no-download]`. With `--no-download`, the client refuses to use
+/// the server's prototypes if they differ from its own.
+fn main() -> ExitCode {
     println!("Hello, world! This is a Scriplets client");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let accepts_prototypes = !args.iter().any(|arg| arg == "--no-download");
+    let address = args
+        .iter()
+        .find(|arg| !arg.starts_with("--"))
+        .cloned()
+        .unwrap_or_else(|| format!("127.0.0.1:{}", DEFAULT_PORT));
+
+    let local_prototypes = load_local_prototypes();
+    let mut stream = match TcpStream::connect(&address) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("Can't connect to {}: {}", address, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match client_handshake(&mut stream, local_prototypes.as_ref(), accepts_prototypes) {
+        Ok(None) => println!("Connected to {}, prototypes match", address),
+        Ok(Some(_)) => println!("Connected to {}, using prototypes of the server", address),
+        Err(e) => {
+            eprintln!("Can't connect to {}: {}", address, e);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
 }
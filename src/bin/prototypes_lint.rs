@@ -0,0 +1,42 @@
+//! Checks prototype packs for problems without starting the game.
+//!
+//! Usage: `prototypes-lint <pack>...`, where each pack is a file or a directory of files, given
+//! in the order they are applied.
+
+use scriplets::prototypes::{read_pack, Prototypes};
+use std::{path::Path, process::ExitCode};
+
+fn main() -> ExitCode {
+    let packs: Vec<String> = std::env::args().skip(1).collect();
+    if packs.is_empty() {
+        eprintln!("usage: prototypes-lint <pack>...");
+        return ExitCode::from(2);
+    }
+    let mut failed = false;
+    let mut fragments = Vec::new();
+    for pack in &packs {
+        match read_pack(Path::new(pack)) {
+            Ok(pack_fragments) => fragments.push(pack_fragments),
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return ExitCode::FAILURE;
+    }
+    let packs: Vec<Vec<_>> = fragments.iter().map(|pack| pack.iter().collect()).collect();
+    match Prototypes::from_packs(&packs) {
+        Ok((_, warnings)) => {
+            for warning in warnings {
+                eprintln!("{}", warning);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
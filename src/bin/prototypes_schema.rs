@@ -0,0 +1,20 @@
+//! Writes the JSON Schema of prototype files, to be used by editors for autocompletion.
+//!
+//! Usage: `prototypes-schema [output file]`, writes to the standard output without a file.
+
+use scriplets::prototypes::schema::prototypes_schema;
+use std::{fs, process::ExitCode};
+
+fn main() -> ExitCode {
+    let schema = serde_json::to_string_pretty(&prototypes_schema()).unwrap();
+    match std::env::args().nth(1) {
+        Some(path) => {
+            if let Err(e) = fs::write(&path, schema + "\n") {
+                eprintln!("{}: can't write the schema: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => println!("{}", schema),
+    }
+    ExitCode::SUCCESS
+}
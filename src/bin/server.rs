@@ -1,9 +1,20 @@
 use std::f32::consts::PI;
+use std::marker::PhantomData;
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
 use scriplets::*;
 use scriplets::program::*;
-use scriplets::prototypes::{ComponentPrototype, Movement, MovementType, Prototypes, PrototypesLoader};
+use scriplets::black_box::DroppedBlackBox;
+use scriplets::handshake::{server_handshake, ServerPrototypes, DEFAULT_PORT, HANDSHAKE_TIMEOUT, MAX_CONCURRENT_HANDSHAKES};
+use scriplets::items::{spawn_world_item, ItemStack, ItemTransfer, TransferError, WorldItem};
+use scriplets::unit::Health;
+use scriplets::prototypes::reapply_prototypes;
+use scriplets::prototypes::{BlackBox, BlackBoxPrototype, BlackBoxReader, Inventory, InventoryPrototype, Item, Manipulator, ManipulatorPrototype, Movement, MovementPrototype, MovementType, Prototype, Prototypes, PrototypesError, PrototypesErrors, PrototypesFragment, PrototypesLoader, Radio, RadioPrototype};
 use bevy::{
-    asset::LoadState,
+    asset::{AssetServerSettings, HandleId, LoadState},
+    ecs::{query::WorldQuery, system::SystemParam},
     input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
     prelude::*,
     render::camera::ScalingMode,
@@ -15,16 +26,18 @@ use bevy_rapier2d::prelude::*;
 const CLEAR_COLOR: Color = Color::rgb(0.1, 0.1, 0.1);
 const RESOLUTION: f32 = 16.0 / 9.0;
 
-pub struct UnitSprite(pub Handle<Image>);
 pub struct WallSprite(pub Handle<Image>);
 
+/// Prototypes clients are checked against, shared with the listener thread.
+pub struct HandshakePrototypes(pub Arc<RwLock<ServerPrototypes>>);
+
 fn spawn_camera(mut commands: Commands) {
     let mut camera = Camera2dBundle::default();
 
     camera.projection.top = 1.0;
     camera.projection.bottom = -1.0;
     camera.projection.right = 1.0 * RESOLUTION;
-    camera.projection.left = -1.0 * RESOLUTION;
+    camera.projection.left = -RESOLUTION;
 
     camera.projection.scaling_mode = ScalingMode::None;
 
@@ -57,39 +70,28 @@ fn move_and_zoom_camera(
     }
 }
 
-fn spawn_unit(
+fn spawn_units(
     mut commands: Commands,
-    unit_sprite: Res<UnitSprite>,
+    asset_server: Res<AssetServer>,
     prototypes_handle: Res<PrototypesHandle>,
     prototypes_assets: Res<Assets<Prototypes>>,
 ) {
-    let component_prototypes = prototypes_assets.get(&prototypes_handle.0).unwrap();
-
-    let unit_program = UnitProgram::new_lua_with_program(
-        r#"
+    let prototypes = prototypes_assets.get(&prototypes_handle.0).unwrap();
+    let program = r#"
         function on_tick(handle)
             handle:move(1, 1)
         end
-    "#
-        .as_bytes(),
-    );
-    let movement = Movement::component_from_pt(component_prototypes, "default").unwrap();
-    commands
-        .spawn()
-        .insert(Unit)
-        .insert(UnitClock(Stopwatch::default()))
-        .insert(movement)
-        .insert(unit_program)
-        .insert(Collider::cuboid(0.499, 0.499))
-        .insert(RigidBody::KinematicPositionBased)
-        .insert_bundle(SpriteBundle {
-            texture: unit_sprite.0.clone(),
-            sprite: Sprite {
-                custom_size: Some(Vec2::splat(1.0)),
-                ..default()
-            },
-            ..default()
-        });
+    "#;
+    if let Err(e) = unit::spawn_unit(
+        &mut commands,
+        prototypes,
+        &asset_server,
+        "default",
+        Transform::default(),
+        program.as_bytes(),
+    ) {
+        error!("Failed to spawn a unit: {}", e);
+    }
 }
 
 fn spawn_walls(mut commands: Commands, wall_sprite: Res<WallSprite>) {
@@ -126,35 +128,33 @@ fn handle_movement(
 ) {
     for (entity, mut movement, mut transform, collider) in units.iter_mut() {
         match movement.movement_type {
-            MovementType::Omnidirectional => {
-                if !movement.hand_brake {
-                    if movement.input_rotation != 0.0 {
-                        let rotation = Quat::from_rotation_z(
-                            -(movement.rotation_speed
-                                * movement.input_rotation.clamp(-1.0, 1.0)
-                                * PI)
-                                / (180.0 * 60.0),
-                        );
-                        transform.rotation *= rotation;
-                    }
-                    if movement.input_move != Vec2::ZERO {
-                        let unrotated_move =
-                            movement.input_move.clamp_length_max(1.0) * (movement.speed / 60.0);
-                        let delta = unrotated_move.rotate(transform.right().truncate());
-                        let shape_pos = transform.translation.truncate();
-                        let shape_rot = transform.rotation.to_euler(EulerRot::XYZ).2;
-                        let max_toi = 1.0;
-                        let filter = QueryFilter::default()
-                            .exclude_collider(entity)
-                            .exclude_sensors();
-                        if rapier_context
-                            .cast_shape(shape_pos, shape_rot, delta, collider, max_toi, filter)
-                            .is_none()
-                        {
-                            transform.translation += delta.extend(0.0);
-                        }
-                        movement.input_move = Vec2::ZERO;
+            MovementType::Omnidirectional if !movement.hand_brake => {
+                if movement.input_rotation != 0.0 {
+                    let rotation = Quat::from_rotation_z(
+                        -(movement.rotation_speed
+                            * movement.input_rotation.clamp(-1.0, 1.0)
+                            * PI)
+                            / (180.0 * 60.0),
+                    );
+                    transform.rotation *= rotation;
+                }
+                if movement.input_move != Vec2::ZERO {
+                    let unrotated_move =
+                        movement.input_move.clamp_length_max(1.0) * (movement.speed / 60.0);
+                    let delta = unrotated_move.rotate(transform.right().truncate());
+                    let shape_pos = transform.translation.truncate();
+                    let shape_rot = transform.rotation.to_euler(EulerRot::XYZ).2;
+                    let max_toi = 1.0;
+                    let filter = QueryFilter::default()
+                        .exclude_collider(entity)
+                        .exclude_sensors();
+                    if rapier_context
+                        .cast_shape(shape_pos, shape_rot, delta, collider, max_toi, filter)
+                        .is_none()
+                    {
+                        transform.translation += delta.extend(0.0);
                     }
+                    movement.input_move = Vec2::ZERO;
                 }
             }
             MovementType::AcceleratedSteering => {
@@ -166,31 +166,31 @@ fn handle_movement(
                 let acceleration = movement.acceleration;
                 let braking_acceleration = -movement.braking_acceleration.unwrap_or(acceleration);
                 let passive_deceleration = movement.passive_deceleration;
-                let is_moving_forward = movement.speed > 0.0;
-                let is_moving_backwards = movement.speed < 0.0;
+                let is_moving_forward = movement.current_speed > 0.0;
+                let is_moving_backwards = movement.current_speed < 0.0;
                 let new_speed = {
                     let acceleration = {
                         if movement.hand_brake {
-                            if movement.speed > 0.0 {
+                            if movement.current_speed > 0.0 {
                                 braking_acceleration
                             } else {
                                 -braking_acceleration
                             }
-                        } else if (movement.speed > 0.0 && input_move_vec.x > 0.0)
-                            || (movement.speed < 0.0 && input_move_vec.x < 0.0)
+                        } else if (movement.current_speed > 0.0 && input_move_vec.x > 0.0)
+                            || (movement.current_speed < 0.0 && input_move_vec.x < 0.0)
                         {
                             acceleration
-                        } else if (movement.speed > 0.0 && input_move_vec.x < 0.0)
-                            || (movement.speed < 0.0 && input_move_vec.x > 0.0)
+                        } else if (movement.current_speed > 0.0 && input_move_vec.x < 0.0)
+                            || (movement.current_speed < 0.0 && input_move_vec.x > 0.0)
                         {
                             braking_acceleration
-                        } else if movement.speed != 0.0 {
+                        } else if movement.current_speed != 0.0 {
                             -passive_deceleration
                         } else {
                             acceleration
                         }
                     };
-                    let new_speed_uncapped = (movement.speed
+                    let new_speed_uncapped = (movement.current_speed
                         + acceleration * input_move_vec.x / 60.0)
                         .clamp(max_speed_backwards, max_speed);
                     if is_moving_forward {
@@ -201,14 +201,14 @@ fn handle_movement(
                         new_speed_uncapped
                     }
                 };
-                movement.speed = new_speed;
-                if movement.speed != 0.0 {
-                    let linear_delta = movement.speed / 60.0;
+                movement.current_speed = new_speed;
+                if movement.current_speed != 0.0 {
+                    let linear_delta = movement.current_speed / 60.0;
                     let starting_translation = transform.translation.truncate()
                         + transform.up().truncate() * movement.rotation_offset;
                     let mut rot_angle =
                         (movement.rotation_speed * PI / (60.0 * 180.0)) * input_move_vec.y;
-                    if movement.speed < 0.0 {
+                    if movement.current_speed < 0.0 {
                         rot_angle = -rot_angle;
                     }
                     let result_rotation = transform.rotation * Quat::from_rotation_z(-rot_angle);
@@ -243,29 +243,218 @@ fn handle_movement(
     }
 }
 
+fn deliver_radio_messages(
+    mut radios: Query<(Entity, &mut Radio, &Transform), With<Unit>>,
+    time: Res<Time>,
+) {
+    let mut broadcasts = Vec::new();
+    for (_, mut radio, transform) in radios.iter_mut() {
+        let origin = transform.translation.truncate();
+        let range = radio.range;
+        broadcasts.extend(
+            radio
+                .outbox
+                .drain(..)
+                .map(|message| (origin, range, message)),
+        );
+        radio.refill_bandwidth(time.delta());
+    }
+    if broadcasts.is_empty() {
+        return;
+    }
+    for (entity, mut radio, transform) in radios.iter_mut() {
+        let position = transform.translation.truncate();
+        for (origin, range, message) in &broadcasts {
+            if message.sender != entity && origin.distance(position) <= *range {
+                radio.receive(message.clone());
+            }
+        }
+    }
+}
+
+/// Parts a unit can have, which its program can use.
+#[derive(WorldQuery)]
+#[world_query(mutable)]
+struct UnitParts<'w> {
+    movement: Option<&'w mut Movement>,
+    radio: Option<&'w mut Radio>,
+    black_box: Option<&'w mut BlackBox>,
+    black_box_reader: Option<&'w BlackBoxReader>,
+    inventory: Option<&'w mut Inventory>,
+    manipulator: Option<&'w mut Manipulator>,
+    health: Option<&'w mut Health>,
+}
+
+/// Item lying in the world.
+#[derive(WorldQuery)]
+struct LyingItem<'w> {
+    entity: Entity,
+    item: &'w WorldItem,
+    transform: &'w Transform,
+}
+
 fn unit_tick(
-    mut units: Query<
-        (
-            &mut UnitProgram,
-            Option<&mut Movement>,
-            &mut UnitClock,
-            &Transform,
-        ),
-        With<Unit>,
-    >,
+    mut units: Query<(Entity, &mut UnitProgram, UnitParts, &UnitClock, &Transform), With<Unit>>,
+    dropped_black_boxes: Query<LyingItem, (With<DroppedBlackBox>, Without<Unit>)>,
+    world_items: Query<LyingItem, Without<Unit>>,
     game_clock: Res<GameClock>,
+    prototypes_handle: Option<Res<PrototypesHandle>>,
+    prototypes_assets: Res<Assets<Prototypes>>,
 ) {
-    for (mut unit_program, mut movement, clock, transform) in units.iter_mut() {
+    // Runs outside of app states, there are no units until prototypes are loaded
+    let prototypes = match prototypes_handle.and_then(|handle| prototypes_assets.get(&handle.0)) {
+        Some(prototypes) => prototypes,
+        None => return,
+    };
+    for (entity, mut unit_program, mut parts, clock, transform) in units.iter_mut() {
+        let nearby_items = match &parts.manipulator {
+            Some(manipulator) => {
+                let position = transform.translation.truncate();
+                let max_distance = manipulator.max_distance();
+                world_items
+                    .iter()
+                    .map(|lying| {
+                        let offset = lying.transform.translation.truncate() - position;
+                        (lying.entity, offset, &lying.item.0)
+                    })
+                    .filter(|(_, offset, _)| offset.length() <= max_distance)
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        let nearby_black_boxes = match parts.black_box_reader {
+            Some(reader) => {
+                let position = transform.translation.truncate();
+                dropped_black_boxes
+                    .iter()
+                    .filter(|dropped| {
+                        dropped.transform.translation.truncate().distance(position) <= reader.range
+                    })
+                    .filter_map(|dropped| {
+                        let data = dropped.item.0.data.as_ref()?;
+                        Some((dropped.entity, &data.payload))
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
         let handle = UnitHandle {
-            movement: movement.as_deref_mut(),
+            entity,
+            movement: parts.movement.as_deref_mut(),
+            radio: parts.radio.as_deref_mut(),
+            black_box: parts.black_box.as_deref_mut(),
+            nearby_black_boxes,
+            inventory: parts.inventory.as_deref_mut(),
+            manipulator: parts.manipulator.as_deref_mut(),
+            nearby_items,
+            health: parts.health.as_deref_mut(),
             transform,
-            clock: &clock,
+            clock,
             game_clock: &game_clock,
+            prototypes,
         };
         unit_program.tick(handle)
     }
 }
 
+/// Inventories of units, along with the prototypes of the items in them.
+#[derive(SystemParam)]
+struct UnitInventories<'w, 's> {
+    inventories: Query<'w, 's, (Entity, &'static mut Inventory, &'static Transform), With<Unit>>,
+    prototypes_handle: Res<'w, PrototypesHandle>,
+    prototypes_assets: Res<'w, Assets<Prototypes>>,
+}
+
+/// Transfer requested by the unit at `origin`.
+struct PendingTransfer {
+    source: Entity,
+    origin: Vec2,
+    range: f32,
+    transfer: ItemTransfer,
+}
+
+fn process_item_transfers(mut units: UnitInventories) {
+    let prototypes = units
+        .prototypes_assets
+        .get(&units.prototypes_handle.0)
+        .unwrap();
+    let mut pending = Vec::new();
+    for (source, mut inventory, transform) in units.inventories.iter_mut() {
+        let origin = transform.translation.truncate();
+        let range = inventory.transfer_range;
+        pending.extend(
+            inventory
+                .pending_transfers
+                .drain(..)
+                .map(|transfer| PendingTransfer {
+                    source,
+                    origin,
+                    range,
+                    transfer,
+                }),
+        );
+    }
+    for transfer in pending {
+        let outcome = transfer_items(&mut units.inventories, prototypes, &transfer);
+        if let Ok((_, mut inventory, _)) = units.inventories.get_mut(transfer.source) {
+            inventory.last_transfer = Some(outcome);
+        }
+    }
+}
+
+/// Move items of a transfer as far as the target has space, returning how many were moved.
+fn transfer_items(
+    inventories: &mut Query<(Entity, &mut Inventory, &Transform), With<Unit>>,
+    prototypes: &Prototypes,
+    pending: &PendingTransfer,
+) -> Result<u32, TransferError> {
+    let transfer = &pending.transfer;
+    let item = Item::from_pt(prototypes, &transfer.item)
+        .ok_or_else(|| TransferError::UnknownItem(transfer.item.clone()))?;
+    let (_, target, transform) = inventories
+        .get(transfer.target)
+        .map_err(|_| TransferError::NoTarget)?;
+    if transform.translation.truncate().distance(pending.origin) > pending.range {
+        return Err(TransferError::OutOfRange);
+    }
+    let space = target.space_for(item);
+    let removed = inventories
+        .get_mut(pending.source)
+        .unwrap()
+        .1
+        .remove(&item.name, transfer.count.min(space));
+    let (_, mut target, _) = inventories.get_mut(transfer.target).unwrap();
+    let mut moved = 0;
+    let mut remainders = Vec::new();
+    for stack in removed {
+        let count = stack.count;
+        match target.insert(item, stack) {
+            Some(remainder) => {
+                moved += count - remainder.count;
+                remainders.push(remainder);
+            }
+            None => moved += count,
+        }
+    }
+    // Items that didn't fit go back where they were taken from, so they always fit
+    let (_, mut source, _) = inventories.get_mut(pending.source).unwrap();
+    for remainder in remainders {
+        source.insert(item, remainder);
+    }
+    Ok(moved)
+}
+
+fn spawn_world_items(mut commands: Commands) {
+    let stacks = [
+        ItemStack::new("iron-plate", 50),
+        ItemStack::new("copper-plate", 50),
+        ItemStack::new("data-card", 1),
+    ];
+    for (i, stack) in stacks.into_iter().enumerate() {
+        spawn_world_item(&mut commands, stack, Vec2::new(2.0 + i as f32, 0.0));
+    }
+}
+
 fn tick_units_clocks(mut units: Query<&mut UnitClock, With<Unit>>, time: Res<Time>) {
     units.iter_mut().for_each(|mut unit| {
         unit.0.tick(time.delta());
@@ -285,33 +474,197 @@ fn print_units_positions(units: Query<&Transform, With<Unit>>) {
     }
 }
 
-fn load_assets(mut commands: Commands, assets: Res<AssetServer>) {
-    let unit_sprite = assets.load("unit.png");
-    commands.insert_resource(UnitSprite(unit_sprite));
+fn load_assets(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    packs: Res<PrototypePacks>,
+    errors: Res<PrototypesErrors>,
+    prototypes_assets: Res<Assets<Prototypes>>,
+) {
     let wall_sprite = assets.load("wall.png");
     commands.insert_resource(WallSprite(wall_sprite));
-    let prototypes = assets.load("prototypes.json");
+    let fragments = packs
+        .0
+        .iter()
+        .map(|pack| match assets.load_folder(pack) {
+            Ok(handles) => handles.into_iter().map(|handle| handle.typed()).collect(),
+            Err(e) => {
+                errors.push(PrototypesError::Pack {
+                    pack: pack.clone(),
+                    message: e.to_string(),
+                });
+                Vec::new()
+            }
+        })
+        .collect();
+    commands.insert_resource(PrototypeFragments(fragments));
+    // Prototypes are assembled from the fragments once all of them are loaded
+    let prototypes = prototypes_assets.get_handle(HandleId::random::<Prototypes>());
     commands.insert_resource(PrototypesHandle(prototypes))
 }
 
+/// Fragments of the prototype packs and where the prototypes assembled from them go.
+#[derive(SystemParam)]
+struct PrototypeAssembly<'w, 's> {
+    fragments: Res<'w, PrototypeFragments>,
+    prototypes: Res<'w, PrototypesHandle>,
+    fragment_assets: Res<'w, Assets<PrototypesFragment>>,
+    prototypes_assets: ResMut<'w, Assets<Prototypes>>,
+    errors: Res<'w, PrototypesErrors>,
+    #[system_param(ignore)]
+    _marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> PrototypeAssembly<'w, 's> {
+    /// Assemble the prototypes from the loaded fragments and replace the previous ones.
+    fn assemble(&mut self) -> Result<(), PrototypesError> {
+        let assembled = self.fragments.assemble(&self.fragment_assets)?;
+        self.prototypes_assets.set_untracked(&self.prototypes.0, assembled);
+        Ok(())
+    }
+}
+
 fn check_load_assets(
     mut state: ResMut<State<AppState>>,
-    unit: Res<UnitSprite>,
     wall: Res<WallSprite>,
-    prototypes: Res<PrototypesHandle>,
+    mut assembly: PrototypeAssembly,
     asset_server: Res<AssetServer>,
 ) {
-    if let LoadState::Loaded =
-        asset_server.get_group_load_state([unit.0.id, wall.0.id, prototypes.0.id])
+    let handles = [wall.0.id]
+        .into_iter()
+        .chain(assembly.fragments.0.iter().flatten().map(|handle| handle.id));
+    match asset_server.get_group_load_state(handles) {
+        _ if !assembly.errors.is_empty() => state.set(AppState::LoadingFailed).unwrap(),
+        LoadState::Loaded => match assembly.assemble() {
+            Ok(()) => state.set(AppState::Playing).unwrap(),
+            Err(e) => {
+                assembly.errors.push(e);
+                state.set(AppState::LoadingFailed).unwrap()
+            }
+        },
+        LoadState::Failed => state.set(AppState::LoadingFailed).unwrap(),
+        _ => {}
+    }
+}
+
+/// Assemble prototypes again when a fragment file changes. Invalid changes are reported and the
+/// previous prototypes are kept.
+fn reload_prototypes(
+    mut events: EventReader<AssetEvent<PrototypesFragment>>,
+    mut assembly: PrototypeAssembly,
+) {
+    for error in assembly.errors.take() {
+        error!("Failed to reload prototypes: {}", error);
+    }
+    if !events
+        .iter()
+        .any(|event| matches!(event, AssetEvent::Modified { .. }))
     {
-        state.set(AppState::Playing).unwrap();
+        return;
+    }
+    match assembly.assemble() {
+        Ok(()) => info!("Prototypes reloaded"),
+        Err(e) => error!("Failed to reload prototypes: {}", e),
     }
 }
 
+/// Accept clients in a separate thread, each of them has to complete the handshake. Clients that
+/// stall are dropped after a timeout and too many clients at once are turned away.
+fn start_listener(
+    mut commands: Commands,
+    prototypes_handle: Res<PrototypesHandle>,
+    prototypes_assets: Res<Assets<Prototypes>>,
+) {
+    let prototypes = prototypes_assets.get(&prototypes_handle.0).unwrap();
+    let shared = Arc::new(RwLock::new(ServerPrototypes::new(prototypes)));
+    commands.insert_resource(HandshakePrototypes(shared.clone()));
+    let handshakes = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        let listener = match TcpListener::bind(("0.0.0.0", DEFAULT_PORT)) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Can't listen on port {}: {}", DEFAULT_PORT, e);
+                return;
+            }
+        };
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept a client: {}", e);
+                    continue;
+                }
+            };
+            let address = stream.peer_addr();
+            if handshakes.fetch_add(1, Ordering::SeqCst) >= MAX_CONCURRENT_HANDSHAKES {
+                handshakes.fetch_sub(1, Ordering::SeqCst);
+                warn!("Turned away client {:?}, too many handshakes at once", address);
+                continue;
+            }
+            let timeouts = stream
+                .set_read_timeout(Some(HANDSHAKE_TIMEOUT))
+                .and_then(|()| stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT)));
+            if let Err(e) = timeouts {
+                handshakes.fetch_sub(1, Ordering::SeqCst);
+                warn!("Failed to set up the connection of client {:?}: {}", address, e);
+                continue;
+            }
+            let prototypes = shared.read().unwrap().clone();
+            let handshakes = handshakes.clone();
+            thread::spawn(move || {
+                match server_handshake(&mut stream, &prototypes) {
+                    Ok(()) => info!("Client {:?} connected", address),
+                    Err(e) => warn!("Handshake with client {:?} failed: {}", address, e),
+                }
+                handshakes.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+}
+
+fn update_handshake_prototypes(
+    mut events: EventReader<AssetEvent<Prototypes>>,
+    handshake_prototypes: Res<HandshakePrototypes>,
+    prototypes_handle: Res<PrototypesHandle>,
+    prototypes_assets: Res<Assets<Prototypes>>,
+) {
+    if events.iter().any(
+        |event| matches!(event, AssetEvent::Modified { handle } if *handle == prototypes_handle.0),
+    ) {
+        let prototypes = prototypes_assets.get(&prototypes_handle.0).unwrap();
+        *handshake_prototypes.0.write().unwrap() = ServerPrototypes::new(prototypes);
+    }
+}
+
+fn report_loading_errors(errors: Res<PrototypesErrors>, mut windows: ResMut<Windows>) {
+    for error in errors.take() {
+        error!("Failed to load prototypes: {}", error);
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_title("Scriplets - failed to load assets, see the log for details".to_string());
+    }
+}
+
+/// The base pack followed by mods named on the command line, in the given order.
+fn prototype_packs() -> PrototypePacks {
+    let mods = std::env::args()
+        .skip(1)
+        .map(|name| format!("mods/{}", name));
+    PrototypePacks(
+        std::iter::once("prototypes".to_string())
+            .chain(mods)
+            .collect(),
+    )
+}
+
 fn main() {
     let height = 900.0;
     let mut app = App::new();
     app.insert_resource(ClearColor(CLEAR_COLOR))
+        .insert_resource(AssetServerSettings {
+            watch_for_changes: cfg!(feature = "hot_reload"),
+            ..default()
+        })
         .insert_resource(WindowDescriptor {
             title: "Scriplets".to_string(),
             present_mode: PresentMode::Fifo,
@@ -323,22 +676,42 @@ fn main() {
         .add_plugins(DefaultPlugins)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(32.0))
         .add_asset::<Prototypes>()
+        .add_asset::<PrototypesFragment>()
         .init_asset_loader::<PrototypesLoader>()
         .add_state(AppState::Loading)
+        .insert_resource(prototype_packs())
         .insert_resource(GameClock(Stopwatch::default()))
         .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_assets))
         .add_system_set(SystemSet::on_update(AppState::Loading).with_system(check_load_assets))
+        .add_system_set(
+            SystemSet::on_enter(AppState::LoadingFailed).with_system(report_loading_errors),
+        )
         .add_system_set(
             SystemSet::on_enter(AppState::Playing)
                 .with_system(spawn_walls)
-                .with_system(spawn_unit)
-                .with_system(spawn_camera),
+                .with_system(spawn_units)
+                .with_system(spawn_world_items)
+                .with_system(spawn_camera)
+                .with_system(start_listener),
         )
         .add_system_set(
             SystemSet::on_update(AppState::Playing)
                 .with_system(print_units_positions)
                 .with_system(game_clock_tick)
                 .with_system(handle_movement)
+                .with_system(deliver_radio_messages)
+                .with_system(process_item_transfers)
+                .with_system(manipulator::process_pickups)
+                .with_system(unit::destroy_dead_units)
+                .with_system(unit::destroy_units)
+                .with_system(reload_prototypes)
+                .with_system(update_handshake_prototypes)
+                .with_system(reapply_prototypes::<MovementPrototype, Movement>)
+                .with_system(reapply_prototypes::<RadioPrototype, Radio>)
+                .with_system(reapply_prototypes::<BlackBoxPrototype, BlackBox>)
+                .with_system(reapply_prototypes::<BlackBoxReader, BlackBoxReader>)
+                .with_system(reapply_prototypes::<InventoryPrototype, Inventory>)
+                .with_system(reapply_prototypes::<ManipulatorPrototype, Manipulator>)
                 .with_system(move_and_zoom_camera),
         )
         .add_system_to_stage(CoreStage::First, tick_units_clocks)
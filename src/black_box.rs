@@ -0,0 +1,73 @@
+//! Black box stores data while a unit is running. When the unit is destroyed, the black box is
+//! dropped where the unit was as an item carrying the stored data. Units with a reader can read
+//! it while it lies in the world, once picked up it's read like any other item carrying data.
+
+use crate::{
+    data_value::{storage::QuotaError, DataValue},
+    items::{ItemData, ItemStack},
+    prototypes::BlackBox,
+};
+use bevy::prelude::*;
+use thiserror::Error;
+
+/// Marks a world item that is a black box dropped by a destroyed unit.
+#[derive(Component)]
+pub struct DroppedBlackBox;
+
+#[derive(Debug, Error)]
+pub enum BlackBoxError {
+    #[error("black box is full: {0}")]
+    Full(#[from] QuotaError),
+}
+
+impl BlackBox {
+    pub fn read(&self, key: &str) -> DataValue {
+        self.data.get(key).cloned().unwrap_or_default()
+    }
+
+    /// Store a value under the key, writing nil removes the key. Fails if the black box would go
+    /// over its capacity, in which case the stored data is left unchanged.
+    pub fn write(&mut self, key: String, value: DataValue) -> Result<(), BlackBoxError> {
+        // Capacity of the prototype can change when it's reloaded
+        self.data.set_capacity(self.capacity);
+        self.data.write(key, value)?;
+        Ok(())
+    }
+
+    /// Amount of bytes taken by the stored data, keys take as many bytes as they are long.
+    pub fn used(&self) -> usize {
+        self.data.used()
+    }
+
+    pub fn to_data_value(&self) -> DataValue {
+        DataValue::Table(
+            self.data
+                .iter()
+                .map(|(k, v)| (DataValue::String(k.clone()), v.clone()))
+                .collect(),
+        )
+    }
+
+    /// Item the black box is dropped as, carrying a table of the stored data.
+    pub fn to_item_stack(&self) -> ItemStack {
+        ItemStack {
+            item: self.item.name().to_string(),
+            count: 1,
+            data: Some(ItemData {
+                payload: self.to_data_value(),
+                key: None,
+            }),
+        }
+    }
+}
+
+/// Value stored under the key in the data of a dropped black box.
+pub fn read_dropped(data: &DataValue, key: &str) -> DataValue {
+    match data {
+        DataValue::Table(table) => table
+            .get(&DataValue::String(key.to_string()))
+            .cloned()
+            .unwrap_or_default(),
+        _ => DataValue::Nil,
+    }
+}
@@ -1,77 +1,248 @@
 //! Enums for representing data stored in data storages. Takes inspiration from mlua's Value.
 
-use bevy::utils::FloatOrd;
 use mlua::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{cmp::Ordering, collections::BTreeMap, ffi::c_void, fmt};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub mod binary;
+pub mod diff;
+pub mod path;
+pub mod storage;
+
+/// Tables nested deeper than this can't be stored. Keeps recursion over values bounded, since
+/// values can come from untrusted programs or the network.
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(untagged)]
 pub enum DataValue {
+    #[default]
     Nil,
     Boolean(bool),
     Integer(i64),
-    #[serde(with = "floatord")]
-    Number(FloatOrd),
+    Number(Number),
     String(String),
+    /// Lua string that isn't valid UTF-8
+    Bytes(#[serde(with = "bytes")] Vec<u8>),
     Sequence(Vec<DataValue>),
     Table(BTreeMap<DataValue, DataValue>),
 }
 
-mod floatord {
-    use bevy::utils::FloatOrd;
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+/// Lua number, totally ordered so it can be a table key. All NaNs are turned into the same NaN,
+/// so they are equal to each other.
+#[derive(Debug, Clone, Copy)]
+pub struct Number(f64);
+
+impl Number {
+    pub fn new(n: f64) -> Self {
+        Self(if n.is_nan() { f64::NAN } else { n })
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
 
-    pub fn serialize<S>(val: &FloatOrd, ser: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        val.0.serialize(ser)
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
+}
 
-    pub fn deserialize<'de, D>(deser: D) -> Result<FloatOrd, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        Ok(FloatOrd(f32::deserialize(deser)?))
+impl Eq for Number {}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-impl Default for DataValue {
-    fn default() -> Self {
-        Self::Nil
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Serialize for Number {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        f64::deserialize(deserializer).map(Self::new)
+    }
+}
+
+/// Byte strings are serialized as bytes and only deserialized from bytes. Formats without byte
+/// strings, like JSON, write them as sequences of integers, which come back as a `Sequence` of
+/// `Integer`s, so only formats with byte strings keep them.
+mod bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(bytes)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+
+    struct BytesVisitor;
+
+    impl<'de> de::Visitor<'de> for BytesVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a byte string")
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+            Ok(bytes.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(bytes)
+        }
+    }
+}
+
+impl DataValue {
+    /// Size of the value in bytes, the length of its binary encoding. Capacities of everything
+    /// storing or sending data values are measured in it.
+    pub fn size(&self) -> usize {
+        self.encoded_size()
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Nil => "nil",
+            Self::Boolean(_) => "boolean",
+            Self::Integer(_) => "integer",
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::Bytes(_) => "bytes",
+            Self::Sequence(_) => "sequence",
+            Self::Table(_) => "table",
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Self::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Integers are numbers too, like in Lua.
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Integer(i) => Some(*i as f64),
+            Self::Number(n) => Some(n.get()),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Strings are byte strings too, like in Lua.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::String(s) => Some(s.as_bytes()),
+            Self::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_sequence(&self) -> Option<&[DataValue]> {
+        match self {
+            Self::Sequence(seq) => Some(seq),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&BTreeMap<DataValue, DataValue>> {
+        match self {
+            Self::Table(table) => Some(table),
+            _ => None,
+        }
     }
 }
 
 impl<'lua> FromLua<'lua> for DataValue {
     fn from_lua(lua_value: LuaValue<'lua>, _lua: &'lua Lua) -> LuaResult<Self> {
-        let type_name = lua_value.type_name();
-        match lua_value {
-            LuaValue::Nil => Ok(Self::Nil),
-            LuaValue::Boolean(b) => Ok(Self::Boolean(b)),
-            LuaValue::Integer(i) => Ok(Self::Integer(i)),
-            LuaValue::Number(n) => Ok(Self::Number(FloatOrd(n as f32))),
-            LuaValue::String(s) => Ok(Self::String(s.to_str()?.into())),
-            LuaValue::Table(t) => {
-                if let Ok(seq) = t
-                    .clone()
-                    .sequence_values::<DataValue>()
-                    .collect::<LuaResult<Vec<DataValue>>>()
-                {
-                    Ok(Self::Sequence(seq))
-                } else {
-                    Ok(Self::Table(
-                        t.pairs()
-                            .collect::<LuaResult<BTreeMap<DataValue, DataValue>>>()?,
-                    ))
-                }
+        from_lua_value(lua_value, &mut Vec::new())
+    }
+}
+
+/// `parents` are the tables the value is in, to catch tables that contain themselves.
+fn from_lua_value(lua_value: LuaValue, parents: &mut Vec<*const c_void>) -> LuaResult<DataValue> {
+    let type_name = lua_value.type_name();
+    let error = |message: String| LuaError::FromLuaConversionError {
+        from: type_name,
+        to: "DataValue",
+        message: Some(message),
+    };
+    match lua_value {
+        LuaValue::Nil => Ok(DataValue::Nil),
+        LuaValue::Boolean(b) => Ok(DataValue::Boolean(b)),
+        LuaValue::Integer(i) => Ok(DataValue::Integer(i)),
+        LuaValue::Number(n) => Ok(DataValue::Number(Number::new(n))),
+        LuaValue::String(s) => Ok(match s.to_str() {
+            Ok(s) => DataValue::String(s.into()),
+            Err(_) => DataValue::Bytes(s.as_bytes().to_vec()),
+        }),
+        LuaValue::Table(ref table) => {
+            let pointer = lua_value.to_pointer();
+            if parents.contains(&pointer) {
+                return Err(error("table contains itself".into()));
+            }
+            if parents.len() == MAX_DEPTH {
+                return Err(error(format!(
+                    "tables are nested deeper than {} levels",
+                    MAX_DEPTH
+                )));
             }
-            _ => Err(LuaError::FromLuaConversionError {
-                from: type_name,
-                to: "DataValue",
-                message: Some("type not supported".into()),
-            }),
+            parents.push(pointer);
+            let mut entries = BTreeMap::new();
+            for pair in table.clone().pairs::<LuaValue, LuaValue>() {
+                let (key, value) = pair?;
+                entries.insert(
+                    from_lua_value(key, parents)?,
+                    from_lua_value(value, parents)?,
+                );
+            }
+            parents.pop();
+            Ok(table_or_sequence(entries))
         }
+        _ => Err(error("type not supported".into())),
+    }
+}
+
+/// Tables with keys from 1 to their length and no other keys are sequences, the empty table
+/// included.
+fn table_or_sequence(entries: BTreeMap<DataValue, DataValue>) -> DataValue {
+    let is_sequence = entries
+        .keys()
+        .enumerate()
+        .all(|(i, key)| *key == DataValue::Integer(i as i64 + 1));
+    if is_sequence {
+        DataValue::Sequence(entries.into_values().collect())
+    } else {
+        DataValue::Table(entries)
     }
 }
 
@@ -81,8 +252,9 @@ impl<'lua> ToLua<'lua> for DataValue {
             Self::Nil => Ok(LuaValue::Nil),
             Self::Boolean(b) => Ok(LuaValue::Boolean(b)),
             Self::Integer(i) => Ok(LuaValue::Integer(i)),
-            Self::Number(n) => Ok(LuaValue::Number(n.0.into())),
+            Self::Number(n) => Ok(LuaValue::Number(n.get())),
             Self::String(s) => s.to_lua(lua),
+            Self::Bytes(b) => lua.create_string(&b).map(LuaValue::String),
             Self::Sequence(seq) => seq.to_lua(lua),
             Self::Table(t) => t.to_lua(lua),
         }
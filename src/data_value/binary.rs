@@ -0,0 +1,242 @@
+//! Compact binary encoding of data values, used to size stored data.
+//!
+//! Every value starts with a tag byte telling its variant. Integers are zigzag encoded LEB128
+//! varints, numbers are little endian 64-bit IEEE 754, lengths of strings, byte strings, sequences
+//! and tables are LEB128 varints followed by the contents. Tables are encoded in key order, so
+//! equal values are always encoded into the same bytes.
+
+use super::{DataValue, Number, MAX_DEPTH};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+const NIL: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+const INTEGER: u8 = 3;
+const NUMBER: u8 = 4;
+const STRING: u8 = 5;
+const SEQUENCE: u8 = 6;
+const TABLE: u8 = 7;
+const BYTES: u8 = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum DecodeError {
+    #[error("data ends in the middle of a value")]
+    UnexpectedEnd,
+    #[error("unknown value tag {0}")]
+    UnknownTag(u8),
+    #[error("varint is longer than 64 bits")]
+    VarintOverflow,
+    #[error("string is not valid UTF-8")]
+    InvalidUtf8,
+    #[error("table has a repeated key")]
+    RepeatedKey,
+    #[error("values are nested deeper than {} levels", MAX_DEPTH)]
+    TooDeep,
+    #[error("{0} bytes left after the value")]
+    TrailingBytes(usize),
+}
+
+impl DataValue {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_size());
+        self.encode(&mut bytes);
+        bytes
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Nil => out.push(NIL),
+            Self::Boolean(false) => out.push(FALSE),
+            Self::Boolean(true) => out.push(TRUE),
+            Self::Integer(i) => {
+                out.push(INTEGER);
+                write_varint(out, zigzag(*i));
+            }
+            Self::Number(n) => {
+                out.push(NUMBER);
+                out.extend_from_slice(&n.get().to_le_bytes());
+            }
+            Self::String(s) => {
+                out.push(STRING);
+                write_varint(out, s.len() as u64);
+                out.extend_from_slice(s.as_bytes());
+            }
+            Self::Bytes(b) => {
+                out.push(BYTES);
+                write_varint(out, b.len() as u64);
+                out.extend_from_slice(b);
+            }
+            Self::Sequence(seq) => {
+                out.push(SEQUENCE);
+                write_varint(out, seq.len() as u64);
+                for value in seq {
+                    value.encode(out);
+                }
+            }
+            Self::Table(table) => {
+                out.push(TABLE);
+                write_varint(out, table.len() as u64);
+                for (key, value) in table {
+                    key.encode(out);
+                    value.encode(out);
+                }
+            }
+        }
+    }
+
+    /// Length of the encoding in bytes, without encoding the value.
+    pub fn encoded_size(&self) -> usize {
+        1 + match self {
+            Self::Nil | Self::Boolean(_) => 0,
+            Self::Integer(i) => varint_size(zigzag(*i)),
+            Self::Number(_) => 8,
+            Self::String(s) => varint_size(s.len() as u64) + s.len(),
+            Self::Bytes(b) => varint_size(b.len() as u64) + b.len(),
+            Self::Sequence(seq) => {
+                varint_size(seq.len() as u64) + seq.iter().map(Self::encoded_size).sum::<usize>()
+            }
+            Self::Table(table) => {
+                varint_size(table.len() as u64)
+                    + table
+                        .iter()
+                        .map(|(key, value)| key.encoded_size() + value.encoded_size())
+                        .sum::<usize>()
+            }
+        }
+    }
+
+    /// Decode a value taking up all of the bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader { bytes, depth: 0 };
+        let value = reader.value()?;
+        match reader.bytes.len() {
+            0 => Ok(value),
+            left => Err(DecodeError::TrailingBytes(left)),
+        }
+    }
+}
+
+fn zigzag(i: i64) -> u64 {
+    ((i << 1) ^ (i >> 63)) as u64
+}
+
+fn unzigzag(u: u64) -> i64 {
+    ((u >> 1) as i64) ^ -((u & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn varint_size(value: u64) -> usize {
+    let bits = (64 - value.leading_zeros() as usize).max(1);
+    bits.div_ceil(7)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    /// Number of sequences and tables the current value is in
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            if bits << shift >> shift != bits {
+                return Err(DecodeError::VarintOverflow);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(DecodeError::VarintOverflow)
+    }
+
+    /// Length of a string or a collection. Every element takes at least a byte, so a length
+    /// larger than the remaining data can't be valid and is rejected before allocating.
+    fn length(&mut self) -> Result<usize, DecodeError> {
+        let len = self.varint()?;
+        if len > self.bytes.len() as u64 {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        Ok(len as usize)
+    }
+
+    fn enter(&mut self) -> Result<(), DecodeError> {
+        if self.depth == MAX_DEPTH {
+            return Err(DecodeError::TooDeep);
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<DataValue, DecodeError> {
+        Ok(match self.byte()? {
+            NIL => DataValue::Nil,
+            FALSE => DataValue::Boolean(false),
+            TRUE => DataValue::Boolean(true),
+            INTEGER => DataValue::Integer(unzigzag(self.varint()?)),
+            NUMBER => {
+                let bytes = self.take(8)?.try_into().expect("8 bytes were taken");
+                DataValue::Number(Number::new(f64::from_le_bytes(bytes)))
+            }
+            STRING => {
+                let len = self.length()?;
+                let bytes = self.take(len)?;
+                let string = std::str::from_utf8(bytes).map_err(|_| DecodeError::InvalidUtf8)?;
+                DataValue::String(string.to_string())
+            }
+            BYTES => {
+                let len = self.length()?;
+                DataValue::Bytes(self.take(len)?.to_vec())
+            }
+            SEQUENCE => {
+                let len = self.length()?;
+                self.enter()?;
+                let mut seq = Vec::with_capacity(len);
+                for _ in 0..len {
+                    seq.push(self.value()?);
+                }
+                self.depth -= 1;
+                DataValue::Sequence(seq)
+            }
+            TABLE => {
+                let len = self.length()?;
+                self.enter()?;
+                let mut table = BTreeMap::new();
+                for _ in 0..len {
+                    let key = self.value()?;
+                    let value = self.value()?;
+                    if table.insert(key, value).is_some() {
+                        return Err(DecodeError::RepeatedKey);
+                    }
+                }
+                self.depth -= 1;
+                DataValue::Table(table)
+            }
+            tag => return Err(DecodeError::UnknownTag(tag)),
+        })
+    }
+}
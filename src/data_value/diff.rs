@@ -0,0 +1,54 @@
+//! Patches of data values: the difference between two values, and applying it to get from one
+//! value to the other.
+//!
+//! A patch is a data value itself. Tables in a patch are merged into tables key by key, nil
+//! removes the key and anything else replaces the value.
+
+use super::DataValue;
+use std::collections::BTreeMap;
+
+impl DataValue {
+    /// Apply a patch made by [`DataValue::diff`], or written by hand.
+    pub fn merge(&mut self, patch: DataValue) {
+        match patch {
+            Self::Table(patch) if matches!(self, Self::Table(_)) => {
+                for (key, value) in patch {
+                    if value == DataValue::Nil {
+                        self.remove_child(&key);
+                    } else if let Some(child) = self.child_mut(&key) {
+                        child.merge(value);
+                    } else {
+                        self.insert_child(key, value);
+                    }
+                }
+            }
+            patch => *self = patch,
+        }
+    }
+
+    /// Patch turning this value into the other one, `None` if they are equal.
+    pub fn diff(&self, other: &DataValue) -> Option<DataValue> {
+        if self == other {
+            return None;
+        }
+        match (self, other) {
+            (Self::Table(old), Self::Table(new)) => {
+                let mut patch = BTreeMap::new();
+                for key in old.keys().filter(|key| !new.contains_key(key)) {
+                    patch.insert(key.clone(), DataValue::Nil);
+                }
+                for (key, value) in new {
+                    let changed = match old.get(key) {
+                        Some(old) => old.diff(value),
+                        None => Some(value.clone()),
+                    };
+                    if let Some(changed) = changed {
+                        patch.insert(key.clone(), changed);
+                    }
+                }
+                Some(DataValue::Table(patch))
+            }
+            _ => Some(other.clone()),
+        }
+    }
+}
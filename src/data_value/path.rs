@@ -0,0 +1,300 @@
+//! Access to values nested in tables and sequences by paths like `inventory.slots[3].count`.
+//!
+//! Paths see data the way Lua does: a sequence is a table with keys from 1 to its length. `.key`
+//! looks up a string key and `[n]` the n-th element counted from 0, which is the integer key
+//! `n + 1`, so mixed tables are indexed the same way as sequences. Changing a table or a sequence
+//! keeps it in the shape converting from Lua would give it, writing nil removes the key.
+
+use super::{table_or_sequence, DataValue};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PathError {
+    #[error("invalid path `{path}`: {reason}")]
+    Invalid { path: String, reason: &'static str },
+    #[error("nothing at `{0}`")]
+    NotFound(String),
+    #[error("expected {expected} at `{path}`, found {found}")]
+    WrongType {
+        path: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    /// Element counted from 0, along with its key in Lua
+    Index {
+        index: usize,
+        key: i64,
+    },
+}
+
+impl Segment {
+    /// Key of the element in Lua.
+    fn key(&self) -> DataValue {
+        match self {
+            Self::Key(key) => DataValue::String(key.clone()),
+            Self::Index { key, .. } => DataValue::Integer(*key),
+        }
+    }
+}
+
+/// Parsed path, the empty path points to the value itself.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Path(pub Vec<Segment>);
+
+impl FromStr for Path {
+    type Err = PathError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| PathError::Invalid {
+            path: path.to_string(),
+            reason,
+        };
+        let mut segments = Vec::new();
+        let mut rest = path;
+        while !rest.is_empty() {
+            if let Some(index) = rest.strip_prefix('[') {
+                let end = index.find(']').ok_or_else(|| invalid("unclosed `[`"))?;
+                let index: usize = index[..end]
+                    .parse()
+                    .map_err(|_| invalid("index has to be a non-negative integer"))?;
+                let key = i64::try_from(index)
+                    .ok()
+                    .and_then(|index| index.checked_add(1))
+                    .ok_or_else(|| invalid("index is too large"))?;
+                segments.push(Segment::Index { index, key });
+                rest = &rest[end + 2..];
+            } else {
+                let key = if segments.is_empty() {
+                    rest
+                } else {
+                    rest.strip_prefix('.')
+                        .ok_or_else(|| invalid("expected `.` or `[`"))?
+                };
+                let end = key.find(&['.', '[', ']'][..]).unwrap_or(key.len());
+                if end == 0 {
+                    return Err(invalid("empty key"));
+                }
+                segments.push(Segment::Key(key[..end].to_string()));
+                rest = &key[end..];
+            }
+        }
+        Ok(Self(segments))
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if i == 0 => write!(f, "{}", key)?,
+                Segment::Key(key) => write!(f, ".{}", key)?,
+                Segment::Index { index, .. } => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Path made of the first segments, for errors.
+fn prefix(segments: &[Segment]) -> String {
+    Path(segments.to_vec()).to_string()
+}
+
+impl DataValue {
+    pub fn get(&self, path: &str) -> Result<&DataValue, PathError> {
+        let path: Path = path.parse()?;
+        let mut value = self;
+        for (i, segment) in path.0.iter().enumerate() {
+            value = value
+                .child(&segment.key())
+                .ok_or_else(|| PathError::NotFound(prefix(&path.0[..=i])))?;
+        }
+        Ok(value)
+    }
+
+    pub fn get_mut(&mut self, path: &str) -> Result<&mut DataValue, PathError> {
+        let path: Path = path.parse()?;
+        let mut value = self;
+        for (i, segment) in path.0.iter().enumerate() {
+            value = value
+                .child_mut(&segment.key())
+                .ok_or_else(|| PathError::NotFound(prefix(&path.0[..=i])))?;
+        }
+        Ok(value)
+    }
+
+    /// Store the value at the path and return the value stored there before. Missing tables on the
+    /// way are created, values that aren't tables or sequences are an error.
+    pub fn set(&mut self, path: &str, value: DataValue) -> Result<Option<DataValue>, PathError> {
+        let path: Path = path.parse()?;
+        let (last, parents) = match path.0.split_last() {
+            Some(split) => split,
+            None => {
+                let previous = std::mem::replace(self, value);
+                return Ok(Some(previous).filter(|previous| *previous != DataValue::Nil));
+            }
+        };
+        let mut container = self;
+        for (i, segment) in parents.iter().enumerate() {
+            let key = segment.key();
+            if !container.is_container() {
+                return Err(not_a_container(&path.0[..i], container));
+            }
+            if container
+                .child(&key)
+                .is_none_or(|child| *child == DataValue::Nil)
+            {
+                if value == DataValue::Nil {
+                    return Ok(None);
+                }
+                // Empty tables are empty sequences
+                container.insert_child(key.clone(), DataValue::Sequence(Vec::new()));
+            }
+            container = container.child_mut(&key).expect("child was inserted");
+        }
+        if !container.is_container() {
+            return Err(not_a_container(parents, container));
+        }
+        Ok(if value == DataValue::Nil {
+            container.remove_child(&last.key())
+        } else {
+            container.insert_child(last.key(), value)
+        })
+    }
+
+    /// Remove the value at the path and return it, nothing at the path isn't an error.
+    pub fn delete(&mut self, path: &str) -> Result<Option<DataValue>, PathError> {
+        self.set(path, DataValue::Nil)
+    }
+
+    pub fn get_bool(&self, path: &str) -> Result<bool, PathError> {
+        self.get_typed(path, "boolean", DataValue::as_bool)
+    }
+
+    pub fn get_integer(&self, path: &str) -> Result<i64, PathError> {
+        self.get_typed(path, "integer", DataValue::as_integer)
+    }
+
+    pub fn get_number(&self, path: &str) -> Result<f64, PathError> {
+        self.get_typed(path, "number", DataValue::as_number)
+    }
+
+    pub fn get_str(&self, path: &str) -> Result<&str, PathError> {
+        self.get_typed(path, "string", DataValue::as_str)
+    }
+
+    pub fn get_bytes(&self, path: &str) -> Result<&[u8], PathError> {
+        self.get_typed(path, "bytes", DataValue::as_bytes)
+    }
+
+    pub fn get_sequence(&self, path: &str) -> Result<&[DataValue], PathError> {
+        self.get_typed(path, "sequence", DataValue::as_sequence)
+    }
+
+    pub fn get_table(&self, path: &str) -> Result<&BTreeMap<DataValue, DataValue>, PathError> {
+        self.get_typed(path, "table", DataValue::as_table)
+    }
+
+    fn get_typed<'a, T>(
+        &'a self,
+        path: &str,
+        expected: &'static str,
+        convert: impl FnOnce(&'a DataValue) -> Option<T>,
+    ) -> Result<T, PathError> {
+        let value = self.get(path)?;
+        convert(value).ok_or_else(|| PathError::WrongType {
+            path: path.to_string(),
+            expected,
+            found: value.type_name(),
+        })
+    }
+
+    fn is_container(&self) -> bool {
+        matches!(self, Self::Sequence(_) | Self::Table(_))
+    }
+
+    /// Element under the Lua key of a table or a sequence.
+    pub(super) fn child(&self, key: &DataValue) -> Option<&DataValue> {
+        match self {
+            Self::Sequence(seq) => sequence_index(seq, key).map(|i| &seq[i]),
+            Self::Table(table) => table.get(key),
+            _ => None,
+        }
+    }
+
+    pub(super) fn child_mut(&mut self, key: &DataValue) -> Option<&mut DataValue> {
+        match self {
+            Self::Sequence(seq) => sequence_index(seq, key).map(|i| &mut seq[i]),
+            Self::Table(table) => table.get_mut(key),
+            _ => None,
+        }
+    }
+
+    /// Store an element of a table or a sequence, values of other types are left unchanged.
+    pub(super) fn insert_child(&mut self, key: DataValue, value: DataValue) -> Option<DataValue> {
+        if let Self::Sequence(seq) = self {
+            if let Some(i) = sequence_index(seq, &key) {
+                return Some(std::mem::replace(&mut seq[i], value));
+            }
+            if key == DataValue::Integer(seq.len() as i64 + 1) {
+                seq.push(value);
+                return None;
+            }
+        }
+        self.change_entries(|entries| entries.insert(key, value))
+    }
+
+    /// Remove an element of a table or a sequence.
+    pub(super) fn remove_child(&mut self, key: &DataValue) -> Option<DataValue> {
+        if let Self::Sequence(seq) = self {
+            match sequence_index(seq, key) {
+                Some(i) if i + 1 == seq.len() => return seq.pop(),
+                Some(_) => {}
+                None => return None,
+            }
+        }
+        self.change_entries(|entries| entries.remove(key))
+    }
+
+    /// Change entries of a table or a sequence as a table and turn it back into the right shape.
+    fn change_entries<T>(
+        &mut self,
+        change: impl FnOnce(&mut BTreeMap<DataValue, DataValue>) -> Option<T>,
+    ) -> Option<T> {
+        let mut entries = match std::mem::take(self) {
+            Self::Sequence(seq) => (1..)
+                .map(DataValue::Integer)
+                .zip(seq)
+                .collect::<BTreeMap<_, _>>(),
+            Self::Table(table) => table,
+            other => {
+                *self = other;
+                return None;
+            }
+        };
+        let result = change(&mut entries);
+        *self = table_or_sequence(entries);
+        result
+    }
+}
+
+fn sequence_index(seq: &[DataValue], key: &DataValue) -> Option<usize> {
+    match key {
+        DataValue::Integer(n) if *n >= 1 && *n as u64 <= seq.len() as u64 => Some(*n as usize - 1),
+        _ => None,
+    }
+}
+
+fn not_a_container(segments: &[Segment], value: &DataValue) -> PathError {
+    PathError::WrongType {
+        path: prefix(segments),
+        expected: "table or sequence",
+        found: value.type_name(),
+    }
+}
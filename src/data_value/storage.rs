@@ -0,0 +1,110 @@
+//! Keyed storage of data values with a capacity, for black boxes and anything else that keeps data
+//! programs write.
+
+use super::DataValue;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{size} bytes needed, but only {available} of {capacity} bytes are free")]
+pub struct QuotaError {
+    pub size: usize,
+    pub available: usize,
+    pub capacity: usize,
+}
+
+/// Data values stored under string keys, taking at most `capacity` bytes. An entry takes as many
+/// bytes as its key is long plus the size of its value.
+#[derive(Debug, Clone, Default)]
+pub struct DataStorage {
+    capacity: usize,
+    used: usize,
+    entries: BTreeMap<String, DataValue>,
+}
+
+impl DataStorage {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the capacity. Stored data is kept even if it doesn't fit anymore, it just can't grow
+    /// until enough is removed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// Amount of bytes taken by the stored data.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn available(&self) -> usize {
+        self.capacity.saturating_sub(self.used)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&DataValue> {
+        self.entries.get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &DataValue)> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Store a value under the key, writing nil removes the key. Returns the value stored before.
+    /// Fails without changing anything if the data would go over the capacity, writes that don't
+    /// make the data larger always succeed.
+    pub fn write(
+        &mut self,
+        key: String,
+        value: DataValue,
+    ) -> Result<Option<DataValue>, QuotaError> {
+        if value == DataValue::Nil {
+            return Ok(self.remove(&key));
+        }
+        let size = entry_size(&key, &value);
+        let previous_size = self
+            .entries
+            .get_key_value(&key)
+            .map_or(0, |(key, value)| entry_size(key, value));
+        let used = self.used - previous_size + size;
+        if used > self.capacity && size > previous_size {
+            return Err(QuotaError {
+                size,
+                available: self.capacity.saturating_sub(self.used - previous_size),
+                capacity: self.capacity,
+            });
+        }
+        self.used = used;
+        Ok(self.entries.insert(key, value))
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<DataValue> {
+        let (key, value) = self.entries.remove_entry(key)?;
+        self.used -= entry_size(&key, &value);
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+}
+
+fn entry_size(key: &str, value: &DataValue) -> usize {
+    key.len() + value.size()
+}
@@ -0,0 +1,251 @@
+//! Handshake done when a client connects to a server, making sure both use the same prototypes.
+//!
+//! Messages are JSON prefixed with their length as a big endian `u32`. The client announces the
+//! hash and manifest of its prototypes. If the hash differs from the server's, the server either
+//! sends its prototypes, when the client accepts them, or refuses the client with a diff.
+//!
+//! The server can't trust what clients send, so their messages are limited to a size that fits
+//! a manifest, and servers should give up on clients that don't finish the handshake in time.
+
+use crate::prototypes::{Manifest, Prototypes, PrototypesError, PrototypesFragment};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    io::{self, Read, Write},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const DEFAULT_PORT: u16 = 7420;
+/// Largest message the server reads from a client
+pub const MAX_CLIENT_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+/// Largest message the client reads from the server, which can carry all prototypes
+pub const MAX_SERVER_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+/// How long the server waits for each read from a client during the handshake
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Most handshakes a server runs at once, clients over the limit are turned away
+pub const MAX_CONCURRENT_HANDSHAKES: usize = 32;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Hello {
+        protocol_version: u32,
+        /// `None` if the client has no prototypes of its own
+        prototypes_hash: Option<String>,
+        manifest: Manifest,
+        accepts_prototypes: bool,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Welcome,
+    /// Server's prototypes, the client has to use them instead of its own
+    Prototypes {
+        hash: String,
+        json: String,
+    },
+    Refused(Refusal),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Error)]
+pub enum Refusal {
+    #[error("protocol version {client} is not supported, the server uses version {server}")]
+    ProtocolVersion { client: u32, server: u32 },
+    #[error("prototypes differ from the server's:\n{0}")]
+    PrototypesMismatch(ManifestDiff),
+}
+
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error("connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("message is {0} bytes, which is more than allowed")]
+    MessageTooLarge(usize),
+    #[error("refused: {0}")]
+    Refused(#[from] Refusal),
+    #[error("prototypes sent by the server are invalid: {0}")]
+    InvalidPrototypes(#[from] PrototypesError),
+    #[error("prototypes sent by the server don't match their hash")]
+    HashMismatch,
+}
+
+/// Prototypes that are on only one side or differ, as `(category, name)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestDiff {
+    pub only_on_server: Vec<(String, String)>,
+    pub only_on_client: Vec<(String, String)>,
+    pub different: Vec<(String, String)>,
+}
+
+impl ManifestDiff {
+    pub fn new(server: &Manifest, client: &Manifest) -> Self {
+        let mut diff = Self::default();
+        for (category, prototypes) in &server.0 {
+            for (name, hash) in prototypes {
+                let key = (category.clone(), name.clone());
+                match client.0.get(category).and_then(|client| client.get(name)) {
+                    None => diff.only_on_server.push(key),
+                    Some(client_hash) if client_hash != hash => diff.different.push(key),
+                    Some(_) => {}
+                }
+            }
+        }
+        for (category, prototypes) in &client.0 {
+            for name in prototypes.keys() {
+                let on_server = server
+                    .0
+                    .get(category)
+                    .is_some_and(|server| server.contains_key(name));
+                if !on_server {
+                    diff.only_on_client.push((category.clone(), name.clone()));
+                }
+            }
+        }
+        diff
+    }
+}
+
+impl Display for ManifestDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("missing on the client", &self.only_on_server),
+            ("missing on the server", &self.only_on_client),
+            ("different", &self.different),
+        ];
+        for (description, prototypes) in sections {
+            for (category, name) in prototypes {
+                writeln!(f, "  {} prototype `{}`: {}", category, name, description)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What the server needs to know about its prototypes to handshake with clients.
+#[derive(Debug, Clone)]
+pub struct ServerPrototypes {
+    pub hash: String,
+    pub manifest: Manifest,
+    pub canonical_json: Arc<String>,
+}
+
+impl ServerPrototypes {
+    pub fn new(prototypes: &Prototypes) -> Self {
+        Self {
+            hash: hash_to_string(prototypes),
+            manifest: prototypes.manifest.clone(),
+            canonical_json: prototypes.canonical_json.clone(),
+        }
+    }
+}
+
+/// Server side of the handshake. Succeeds if the client ends up using the server's prototypes.
+pub fn server_handshake<S: Read + Write>(
+    stream: &mut S,
+    prototypes: &ServerPrototypes,
+) -> Result<(), HandshakeError> {
+    let ClientMessage::Hello {
+        protocol_version,
+        prototypes_hash,
+        manifest,
+        accepts_prototypes,
+    } = read_message(stream, MAX_CLIENT_MESSAGE_SIZE)?;
+    let response = if protocol_version != PROTOCOL_VERSION {
+        ServerMessage::Refused(Refusal::ProtocolVersion {
+            client: protocol_version,
+            server: PROTOCOL_VERSION,
+        })
+    } else if prototypes_hash.as_ref() == Some(&prototypes.hash) {
+        ServerMessage::Welcome
+    } else if accepts_prototypes {
+        ServerMessage::Prototypes {
+            hash: prototypes.hash.clone(),
+            json: prototypes.canonical_json.to_string(),
+        }
+    } else {
+        ServerMessage::Refused(Refusal::PrototypesMismatch(ManifestDiff::new(
+            &prototypes.manifest,
+            &manifest,
+        )))
+    };
+    write_message(stream, &response)?;
+    match response {
+        ServerMessage::Refused(refusal) => Err(refusal.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Client side of the handshake. Returns the server's prototypes if they were sent because the
+/// client's own prototypes differ, `None` if the client's prototypes can be used.
+pub fn client_handshake<S: Read + Write>(
+    stream: &mut S,
+    prototypes: Option<&Prototypes>,
+    accepts_prototypes: bool,
+) -> Result<Option<Prototypes>, HandshakeError> {
+    let hello = ClientMessage::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        prototypes_hash: prototypes.map(hash_to_string),
+        manifest: prototypes.map_or_else(Manifest::default, |p| p.manifest.clone()),
+        accepts_prototypes,
+    };
+    write_message(stream, &hello)?;
+    match read_message(stream, MAX_SERVER_MESSAGE_SIZE)? {
+        ServerMessage::Welcome => Ok(None),
+        ServerMessage::Prototypes { hash, json } => {
+            let fragment = PrototypesFragment::from_json(json.as_bytes(), Path::new("<server>"))?;
+            let (received, _) = Prototypes::from_packs(&[vec![&fragment]])?;
+            if hash_to_string(&received) != hash {
+                return Err(HandshakeError::HashMismatch);
+            }
+            Ok(Some(received))
+        }
+        ServerMessage::Refused(refusal) => Err(refusal.into()),
+    }
+}
+
+fn hash_to_string(prototypes: &Prototypes) -> String {
+    prototypes
+        .hash
+        .map(|hash| hash.to_hex().to_string())
+        .unwrap_or_default()
+}
+
+pub fn write_message<W: Write, T: Serialize>(
+    writer: &mut W,
+    message: &T,
+) -> Result<(), HandshakeError> {
+    let bytes = serde_json::to_vec(message)?;
+    if bytes.len() > MAX_SERVER_MESSAGE_SIZE {
+        return Err(HandshakeError::MessageTooLarge(bytes.len()));
+    }
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read a message of at most `max_size` bytes. The buffer grows as the message arrives, so a
+/// peer only announcing a large message doesn't get memory allocated for it.
+pub fn read_message<R: Read, T: DeserializeOwned>(
+    reader: &mut R,
+    max_size: usize,
+) -> Result<T, HandshakeError> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > max_size {
+        return Err(HandshakeError::MessageTooLarge(length));
+    }
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < length {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(serde_json::from_slice(&bytes)?)
+}
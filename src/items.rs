@@ -0,0 +1,257 @@
+//! Items and operations on inventories holding them.
+
+use crate::{
+    data_value::DataValue,
+    prototypes::{Inventory, Item, Prototype, Prototypes},
+};
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32,
+    /// Only items that can carry data have it, such items don't stack
+    pub data: Option<ItemData>,
+}
+
+/// Data written to an item. Data locked with a key can only be accessed by presenting the same
+/// key. No actual encryption is done.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ItemData {
+    pub payload: DataValue,
+    pub key: Option<String>,
+}
+
+/// Items lying in the world that can be picked up by units with a manipulator.
+#[derive(Component, Debug, Clone)]
+pub struct WorldItem(pub ItemStack);
+
+/// Put a stack of items into the world, lying at the position.
+pub fn spawn_world_item<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    stack: ItemStack,
+    position: Vec2,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut entity = commands.spawn();
+    entity
+        .insert(WorldItem(stack))
+        .insert_bundle(TransformBundle::from(Transform::from_translation(
+            position.extend(0.0),
+        )));
+    entity
+}
+
+/// Request to move items from one unit's inventory to another's, processed after units tick.
+#[derive(Debug, Clone)]
+pub struct ItemTransfer {
+    pub target: Entity,
+    pub item: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum TransferError {
+    #[error("item `{0}` doesn't exist")]
+    UnknownItem(String),
+    #[error("target unit doesn't exist or has no inventory")]
+    NoTarget,
+    #[error("target unit is out of transfer range")]
+    OutOfRange,
+}
+
+#[derive(Debug, Error)]
+pub enum ItemDataError {
+    #[error("no item in the slot")]
+    EmptySlot,
+    #[error("item `{0}` can't carry data")]
+    NoDataStorage(String),
+    #[error("access denied: wrong key")]
+    WrongKey,
+    #[error("data is {size} bytes, but the item can only hold {capacity} bytes")]
+    TooLarge { size: usize, capacity: usize },
+}
+
+impl ItemStack {
+    pub fn new(item: &str, count: u32) -> Self {
+        Self {
+            item: item.to_string(),
+            count,
+            data: None,
+        }
+    }
+}
+
+impl ItemData {
+    pub fn check_key(&self, key: Option<&str>) -> Result<(), ItemDataError> {
+        match &self.key {
+            Some(expected) if Some(expected.as_str()) != key => Err(ItemDataError::WrongKey),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Inventory {
+    /// How many items of the kind can be put into one slot of this inventory.
+    pub fn stack_size(&self, item: &Item) -> u32 {
+        if item.data_capacity.is_some() {
+            return 1;
+        }
+        self.max_stack_size
+            .map_or(item.stack_size, |max| max.min(item.stack_size))
+    }
+
+    pub fn count(&self, item: &str) -> u32 {
+        self.stacks
+            .iter()
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    /// How many items of the kind can still be inserted.
+    pub fn space_for(&self, item: &Item) -> u32 {
+        let stack_size = self.stack_size(item);
+        let in_partial_stacks: u32 = self
+            .stacks
+            .iter()
+            .filter(|stack| stack.item == item.name)
+            .map(|stack| stack_size.saturating_sub(stack.count))
+            .sum();
+        let free_slots = self.slots.saturating_sub(self.stacks.len()) as u32;
+        in_partial_stacks.saturating_add(free_slots.saturating_mul(stack_size))
+    }
+
+    /// Insert a stack of items, filling existing stacks first. Returns what didn't fit.
+    pub fn insert(&mut self, item: &Item, mut stack: ItemStack) -> Option<ItemStack> {
+        let stack_size = self.stack_size(item);
+        if stack.data.is_none() {
+            for existing in self
+                .stacks
+                .iter_mut()
+                .filter(|existing| existing.item == item.name && existing.data.is_none())
+            {
+                let added = stack.count.min(stack_size.saturating_sub(existing.count));
+                existing.count += added;
+                stack.count -= added;
+            }
+        }
+        while stack.count > 0 && self.stacks.len() < self.slots && stack_size > 0 {
+            let added = stack.count.min(stack_size);
+            self.stacks.push(ItemStack {
+                item: item.name.clone(),
+                count: added,
+                data: stack.data.take(),
+            });
+            stack.count -= added;
+        }
+        if stack.count > 0 {
+            Some(stack)
+        } else {
+            None
+        }
+    }
+
+    /// Remove up to `count` items, taking from the last stacks first. Returns the removed items,
+    /// items carrying data are returned as separate stacks.
+    pub fn remove(&mut self, item: &str, count: u32) -> Vec<ItemStack> {
+        let mut removed = Vec::new();
+        let mut left = count;
+        for stack in self
+            .stacks
+            .iter_mut()
+            .rev()
+            .filter(|stack| stack.item == item)
+        {
+            if left == 0 {
+                break;
+            }
+            let taken = left.min(stack.count);
+            stack.count -= taken;
+            left -= taken;
+            removed.push(ItemStack {
+                item: item.to_string(),
+                count: taken,
+                data: if stack.count == 0 {
+                    stack.data.take()
+                } else {
+                    None
+                },
+            });
+        }
+        self.stacks.retain(|stack| stack.count > 0);
+        removed
+    }
+
+    /// Read data of the item in the slot, slots are counted from 0.
+    pub fn read_item_data(
+        &self,
+        prototypes: &Prototypes,
+        slot: usize,
+        key: Option<&str>,
+    ) -> Result<DataValue, ItemDataError> {
+        let stack = self.stacks.get(slot).ok_or(ItemDataError::EmptySlot)?;
+        match &stack.data {
+            Some(data) => {
+                data.check_key(key)?;
+                Ok(data.payload.clone())
+            }
+            None => {
+                Self::data_capacity(prototypes, &stack.item)?;
+                Ok(DataValue::Nil)
+            }
+        }
+    }
+
+    /// Write data to the item in the slot, slots are counted from 0.
+    pub fn write_item_data(
+        &mut self,
+        prototypes: &Prototypes,
+        slot: usize,
+        payload: DataValue,
+        key: Option<&str>,
+    ) -> Result<(), ItemDataError> {
+        let stack = self.data_stack_mut(prototypes, slot, key)?;
+        let capacity = Self::data_capacity(prototypes, &stack.item)?;
+        let size = payload.size();
+        if size > capacity {
+            return Err(ItemDataError::TooLarge { size, capacity });
+        }
+        stack.data.get_or_insert_with(Default::default).payload = payload;
+        Ok(())
+    }
+
+    /// Lock the data of the item in the slot with a new key, or unlock it with `None`. Current
+    /// key has to be presented if the data is locked.
+    pub fn set_item_key(
+        &mut self,
+        prototypes: &Prototypes,
+        slot: usize,
+        new_key: Option<String>,
+        key: Option<&str>,
+    ) -> Result<(), ItemDataError> {
+        let stack = self.data_stack_mut(prototypes, slot, key)?;
+        stack.data.get_or_insert_with(Default::default).key = new_key;
+        Ok(())
+    }
+
+    fn data_stack_mut(
+        &mut self,
+        prototypes: &Prototypes,
+        slot: usize,
+        key: Option<&str>,
+    ) -> Result<&mut ItemStack, ItemDataError> {
+        let stack = self.stacks.get_mut(slot).ok_or(ItemDataError::EmptySlot)?;
+        Self::data_capacity(prototypes, &stack.item)?;
+        if let Some(data) = &stack.data {
+            data.check_key(key)?;
+        }
+        Ok(stack)
+    }
+
+    fn data_capacity(prototypes: &Prototypes, item: &str) -> Result<usize, ItemDataError> {
+        Item::from_pt(prototypes, item)
+            .and_then(|item| item.data_capacity)
+            .ok_or_else(|| ItemDataError::NoDataStorage(item.to_string()))
+    }
+}
@@ -2,42 +2,73 @@ use bevy::{
     prelude::*,
     time::Stopwatch,
 };
-use prototypes::{Movement, Prototypes};
+use prototypes::{Movement, Prototypes, PrototypesError, PrototypesFragment};
 
+pub mod black_box;
 pub mod data_value;
+pub mod handshake;
+pub mod items;
+pub mod manipulator;
 pub mod program;
 pub mod prototypes;
+pub mod radio;
+pub mod unit;
 
 // General TODO list
 // - split into client and server
 // - code editing gui
 
 // General ideas
-//  Black box: a component that can store data when unit is running and extracted from a unit
-//  corpse as an item and be read by other units.
-//
-//  Items
-//  Units with manipulators specify an area that they want to pick up from. They are given a list
-//  of what can be picked up and then they choose what is picked up
-//
-//  Items with data
-//  Similar to black box, can have data written and read. Can be encrypted. No actual encryption
-//  will be done, just comparing the keys.
-//
 //  Possible new language: wasm
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum AppState {
     Loading,
+    LoadingFailed,
     Playing,
 }
 
 #[derive(Component)]
 pub struct Unit;
 
+/// Marks a destroyed unit. The unit is removed from the world on the frame after it's marked,
+/// dropping its black box and items where it was.
+#[derive(Component)]
+pub struct Destroyed;
+
 #[derive(Component)]
 pub struct UnitClock(pub Stopwatch);
 
 pub struct GameClock(pub Stopwatch);
 
 pub struct PrototypesHandle(pub Handle<Prototypes>);
+
+/// Asset folders with prototypes, in the order they are applied. Later packs can override or
+/// remove prototypes of earlier ones.
+pub struct PrototypePacks(pub Vec<String>);
+
+/// Fragments loaded from each of the prototype packs.
+pub struct PrototypeFragments(pub Vec<Vec<Handle<PrototypesFragment>>>);
+
+impl PrototypeFragments {
+    /// Assemble prototypes from the loaded fragments, logging warnings.
+    pub fn assemble(
+        &self,
+        fragment_assets: &Assets<PrototypesFragment>,
+    ) -> Result<Prototypes, PrototypesError> {
+        let packs: Vec<Vec<_>> = self
+            .0
+            .iter()
+            .map(|pack| {
+                pack.iter()
+                    .filter_map(|handle| fragment_assets.get(handle))
+                    .collect()
+            })
+            .collect();
+        let (prototypes, warnings) = Prototypes::from_packs(&packs)?;
+        for warning in warnings {
+            warn!("{}", warning);
+        }
+        Ok(prototypes)
+    }
+}
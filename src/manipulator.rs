@@ -0,0 +1,178 @@
+//! Manipulators pick up items lying in the world. Picking up is done in two steps: the unit scans
+//! an area in reach of the manipulator and gets the list of items there, then chooses which one of
+//! them to pick up.
+
+use crate::{
+    items::{ItemStack, WorldItem},
+    prototypes::{Inventory, Item, Manipulator, PickupArea, Prototype, Prototypes},
+    PrototypesHandle, Unit,
+};
+use bevy::prelude::*;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error)]
+pub enum ManipulatorError {
+    #[error("area is {distance} tiles away, but the manipulator can only reach {reach} tiles")]
+    OutOfReach { distance: f32, reach: f32 },
+    #[error("item wasn't found in the last scanned area")]
+    NotScanned,
+    #[error("manipulator is busy")]
+    Busy,
+    #[error("item is {distance} tiles away, but the manipulator can only pick up items {max_distance} tiles away")]
+    ItemOutOfReach { distance: f32, max_distance: f32 },
+    #[error("item is no longer there")]
+    ItemGone,
+    #[error("item `{0}` doesn't exist")]
+    UnknownItem(String),
+    #[error("unit has no inventory")]
+    NoInventory,
+}
+
+impl PickupArea {
+    /// Whether a point, relative to the area center, is inside the area.
+    pub fn contains(&self, offset: Vec2) -> bool {
+        match *self {
+            Self::Circle { radius } => offset.length() <= radius,
+            Self::Rectangle { width, height } => {
+                offset.x.abs() <= width / 2.0 && offset.y.abs() <= height / 2.0
+            }
+        }
+    }
+
+    /// Distance from the area center to its farthest point.
+    pub fn extent(&self) -> f32 {
+        match *self {
+            Self::Circle { radius } => radius,
+            Self::Rectangle { width, height } => Vec2::new(width, height).length() / 2.0,
+        }
+    }
+}
+
+impl Manipulator {
+    /// Maximum distance from the unit at which items can be picked up.
+    pub fn max_distance(&self) -> f32 {
+        self.reach + self.area.extent()
+    }
+
+    /// List items in the area centered at `center`, relative to the unit. `nearby_items` are
+    /// items around the unit, with positions relative to the unit. Only items from the last scan
+    /// can be picked up.
+    pub fn scan<'a>(
+        &mut self,
+        center: Vec2,
+        nearby_items: &[(Entity, Vec2, &'a ItemStack)],
+    ) -> Result<Vec<(Entity, &'a ItemStack)>, ManipulatorError> {
+        let distance = center.length();
+        if distance > self.reach {
+            return Err(ManipulatorError::OutOfReach {
+                distance,
+                reach: self.reach,
+            });
+        }
+        let found: Vec<(Entity, &ItemStack)> = nearby_items
+            .iter()
+            .filter(|(_, position, _)| self.area.contains(*position - center))
+            .map(|(entity, _, stack)| (*entity, *stack))
+            .collect();
+        self.scanned = found.iter().map(|(entity, _)| *entity).collect();
+        Ok(found)
+    }
+
+    /// Choose an item from the last scan to be picked up after units tick.
+    pub fn pick_up(&mut self, item: Entity) -> Result<(), ManipulatorError> {
+        if self.cooldown > 0.0 || self.pending_pickup.is_some() {
+            return Err(ManipulatorError::Busy);
+        }
+        if !self.scanned.contains(&item) {
+            return Err(ManipulatorError::NotScanned);
+        }
+        self.pending_pickup = Some(item);
+        Ok(())
+    }
+
+    pub fn tick_cooldown(&mut self, delta: Duration) {
+        self.cooldown = (self.cooldown - delta.as_secs_f32()).max(0.0);
+    }
+}
+
+/// Move items chosen by manipulators into the inventories of their units, as many as fit. Items
+/// picked up entirely are removed from the world. The outcome is kept in `last_pickup`.
+pub fn process_pickups(
+    mut commands: Commands,
+    mut units: Query<(&mut Manipulator, Option<&mut Inventory>, &Transform), With<Unit>>,
+    mut world_items: Query<(&mut WorldItem, &Transform), Without<Unit>>,
+    prototypes_handle: Res<PrototypesHandle>,
+    prototypes_assets: Res<Assets<Prototypes>>,
+    time: Res<Time>,
+) {
+    let prototypes = prototypes_assets.get(&prototypes_handle.0).unwrap();
+    for (mut manipulator, inventory, transform) in units.iter_mut() {
+        manipulator.tick_cooldown(time.delta());
+        let target = match manipulator.pending_pickup.take() {
+            Some(target) => target,
+            None => continue,
+        };
+        let outcome = match inventory {
+            Some(mut inventory) => pick_up(
+                &mut commands,
+                &manipulator,
+                &mut inventory,
+                transform,
+                target,
+                &mut world_items,
+                prototypes,
+            ),
+            None => Err(ManipulatorError::NoInventory),
+        };
+        if outcome.is_ok() {
+            manipulator.cooldown = 1.0 / manipulator.speed;
+        }
+        manipulator.last_pickup = Some(outcome);
+    }
+}
+
+/// Move the item lying at `target` into the inventory, returning how many were picked up. The
+/// unit may have moved since the scan, so the item has to still be in reach.
+fn pick_up(
+    commands: &mut Commands,
+    manipulator: &Manipulator,
+    inventory: &mut Inventory,
+    transform: &Transform,
+    target: Entity,
+    world_items: &mut Query<(&mut WorldItem, &Transform), Without<Unit>>,
+    prototypes: &Prototypes,
+) -> Result<u32, ManipulatorError> {
+    let (mut world_item, item_transform) = world_items
+        .get_mut(target)
+        .map_err(|_| ManipulatorError::ItemGone)?;
+    // Items picked up entirely earlier this frame are empty until they are despawned
+    if world_item.0.count == 0 {
+        return Err(ManipulatorError::ItemGone);
+    }
+    let distance = transform
+        .translation
+        .truncate()
+        .distance(item_transform.translation.truncate());
+    if distance > manipulator.max_distance() {
+        return Err(ManipulatorError::ItemOutOfReach {
+            distance,
+            max_distance: manipulator.max_distance(),
+        });
+    }
+    let item = Item::from_pt(prototypes, &world_item.0.item)
+        .ok_or_else(|| ManipulatorError::UnknownItem(world_item.0.item.clone()))?;
+    let stack = std::mem::replace(&mut world_item.0, ItemStack::new(&item.name, 0));
+    let count = stack.count;
+    match inventory.insert(item, stack) {
+        Some(remainder) => {
+            let picked_up = count - remainder.count;
+            world_item.0 = remainder;
+            Ok(picked_up)
+        }
+        None => {
+            commands.entity(target).despawn();
+            Ok(count)
+        }
+    }
+}
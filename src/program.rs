@@ -1,4 +1,12 @@
-use super::{GameClock, Movement, UnitClock};
+use super::{
+    black_box,
+    data_value::DataValue,
+    items::{ItemDataError, ItemStack, ItemTransfer},
+    prototypes::{BlackBox, Inventory, Manipulator, MovementType, Processor, Prototypes, Radio},
+    radio::RadioMessage,
+    unit::Health,
+    GameClock, Movement, UnitClock,
+};
 use bevy::prelude::*;
 use mlua::prelude::*;
 use std::{f32::consts::PI, sync::Mutex};
@@ -7,6 +15,8 @@ use std::{f32::consts::PI, sync::Mutex};
 pub struct UnitProgram {
     state: UnitProgramState,
     pub program: Box<[u8]>,
+    /// Set by the processor running the program
+    memory_limit: Option<usize>,
 }
 
 impl UnitProgram {
@@ -15,13 +25,21 @@ impl UnitProgram {
     }
 
     pub fn reload(&mut self) {
-        self.state.reload(self.program.as_ref())
+        match self.memory_limit {
+            Some(memory_limit) => {
+                self.state = self.state.resetted();
+                self.state.set_memory_limit(memory_limit);
+                self.state.load(self.program.as_ref());
+            }
+            None => self.state.reload(self.program.as_ref()),
+        }
     }
 
     pub fn new_lua() -> Self {
         UnitProgram {
             state: UnitProgramState::new_lua(),
             program: Box::new([]),
+            memory_limit: None,
         }
     }
 
@@ -29,8 +47,20 @@ impl UnitProgram {
         UnitProgram {
             state: UnitProgramState::new_lua_with_program(program),
             program: program.into(),
+            memory_limit: None,
         }
     }
+
+    /// Program running on a processor, limited by its characteristics.
+    pub fn new_lua_for_processor(processor: &Processor, program: &[u8]) -> Self {
+        let mut result = UnitProgram {
+            state: UnitProgramState::new_lua(),
+            program: program.into(),
+            memory_limit: Some(processor.memory_limit),
+        };
+        result.reload();
+        result
+    }
 }
 
 pub enum UnitProgramState {
@@ -39,23 +69,49 @@ pub enum UnitProgramState {
 }
 
 impl UnitProgramState {
-    pub fn tick(&mut self, handle: UnitHandle<'_>) {
-        // TODO: error handling?
+    /// Run the callbacks of the program. Errors raised by a callback are logged and don't stop
+    /// the other callbacks from running.
+    pub fn tick(&mut self, mut handle: UnitHandle<'_>) {
+        let entity = handle.entity;
         match self {
             Self::Lua(lua) => {
                 let lua = lua.get_mut().unwrap();
-                if let Some(on_tick_fn) = lua
-                    .globals()
-                    .get::<_, Option<LuaFunction>>("on_tick")
-                    .unwrap()
-                {
-                    lua.scope(|s| {
-                        let lua_handle = s.create_nonstatic_userdata(LuaUnitHandle { handle })?;
-                        on_tick_fn.call(lua_handle)?;
-                        Ok(())
-                    })
-                    .unwrap();
+                let globals = lua.globals();
+                let on_message_fn = globals.get::<_, Option<LuaFunction>>("on_message").unwrap();
+                let on_tick_fn = globals.get::<_, Option<LuaFunction>>("on_tick").unwrap();
+                // Without an on_message callback, messages stay in the mailbox to be polled
+                let messages: Vec<RadioMessage> = match (&on_message_fn, &mut handle.radio) {
+                    (Some(_), Some(radio)) => radio.mailbox.drain(..).collect(),
+                    _ => Vec::new(),
                 };
+                if on_tick_fn.is_none() && messages.is_empty() {
+                    return;
+                }
+                let result = lua.scope(|s| {
+                    let lua_handle = s.create_nonstatic_userdata(LuaUnitHandle { handle })?;
+                    if let Some(on_message_fn) = on_message_fn {
+                        for message in messages {
+                            let result = on_message_fn.call::<_, ()>((
+                                lua_handle.clone(),
+                                message.channel,
+                                message.data,
+                                message.sender.to_bits(),
+                            ));
+                            if let Err(e) = result {
+                                log_callback_error(entity, "on_message", &e);
+                            }
+                        }
+                    }
+                    if let Some(on_tick_fn) = on_tick_fn {
+                        if let Err(e) = on_tick_fn.call::<_, ()>(lua_handle) {
+                            log_callback_error(entity, "on_tick", &e);
+                        }
+                    }
+                    Ok(())
+                });
+                if let Err(e) = result {
+                    error!("Failed to run the program of unit {:?}: {}", entity, e);
+                }
             }
         }
     }
@@ -82,21 +138,64 @@ impl UnitProgramState {
 
     pub fn new_lua_with_program(program: &[u8]) -> Self {
         let mut result = Self::new_lua();
-        match result {
-            Self::Lua(ref lua) => {
-                let lua = lua.lock().unwrap();
+        result.load(program);
+        result
+    }
+
+    /// Run the program in the current state.
+    pub fn load(&mut self, program: &[u8]) {
+        match self {
+            Self::Lua(lua) => {
+                let lua = lua.get_mut().unwrap();
                 lua.load(program).exec().unwrap();
             }
-        };
-        result
+        }
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        match self {
+            Self::Lua(lua) => {
+                lua.get_mut()
+                    .unwrap()
+                    .set_memory_limit(memory_limit)
+                    .unwrap();
+            }
+        }
     }
 }
 
 pub struct UnitHandle<'a> {
+    pub entity: Entity,
     pub movement: Option<&'a mut Movement>,
+    pub radio: Option<&'a mut Radio>,
+    pub black_box: Option<&'a mut BlackBox>,
+    /// Data of dropped black boxes in range of the unit's black box reader
+    pub nearby_black_boxes: Vec<(Entity, &'a DataValue)>,
+    pub inventory: Option<&'a mut Inventory>,
+    pub manipulator: Option<&'a mut Manipulator>,
+    /// Items in the world in reach of the unit's manipulator, with positions relative to the unit
+    pub nearby_items: Vec<(Entity, Vec2, &'a ItemStack)>,
+    pub health: Option<&'a mut Health>,
     pub transform: &'a Transform,
     pub clock: &'a UnitClock,
     pub game_clock: &'a GameClock,
+    pub prototypes: &'a Prototypes,
+}
+
+fn log_callback_error(entity: Entity, callback: &str, error: &LuaError) {
+    warn!(
+        "`{}` of the program of unit {:?} failed: {}",
+        callback, entity, error
+    );
+}
+
+fn log_item_data_error(entity: Entity, slot: usize, error: &ItemDataError) {
+    if let ItemDataError::WrongKey = error {
+        warn!(
+            "Unit {:?} presented a wrong key for the data of the item in slot {}",
+            entity, slot
+        );
+    }
 }
 
 pub struct LuaUnitHandle<'a> {
@@ -124,10 +223,262 @@ impl LuaUserData for LuaUnitHandle<'_> {
                 movement.hand_brake = !movement.hand_brake;
             }
             Ok(())
+        });
+        methods.add_method_mut(
+            "send",
+            |_lua, lua_handle, (channel, data): (u32, DataValue)| {
+                let sender = lua_handle.handle.entity;
+                match &mut lua_handle.handle.radio {
+                    Some(radio) => match radio.send(sender, channel, data) {
+                        Ok(()) => Ok((true, None)),
+                        Err(e) => Ok((false, Some(e.to_string()))),
+                    },
+                    None => Ok((false, Some("unit has no radio".to_string()))),
+                }
+            },
+        );
+        methods.add_method_mut("receive", |lua, lua_handle, ()| {
+            if let Some(message) = lua_handle
+                .handle
+                .radio
+                .as_mut()
+                .and_then(|radio| radio.mailbox.pop_front())
+            {
+                let table = lua.create_table()?;
+                table.set("channel", message.channel)?;
+                table.set("data", message.data)?;
+                table.set("sender", message.sender.to_bits())?;
+                Ok(LuaValue::Table(table))
+            } else {
+                Ok(LuaValue::Nil)
+            }
+        });
+        methods.add_method_mut(
+            "black_box_write",
+            |_lua, lua_handle, (key, value): (String, DataValue)| {
+                if let Some(black_box) = &mut lua_handle.handle.black_box {
+                    match black_box.write(key, value) {
+                        Ok(()) => Ok((true, None)),
+                        Err(e) => Ok((false, Some(e.to_string()))),
+                    }
+                } else {
+                    Ok((false, Some("unit has no black box".to_string())))
+                }
+            },
+        );
+        methods.add_method("black_box_read", |_lua, lua_handle, key: String| {
+            Ok(lua_handle
+                .handle
+                .black_box
+                .as_ref()
+                .map(|black_box| black_box.read(&key))
+                .unwrap_or_default())
+        });
+        methods.add_method("scan_black_boxes", |_lua, lua_handle, ()| {
+            Ok(lua_handle
+                .handle
+                .nearby_black_boxes
+                .iter()
+                .map(|(entity, _)| entity.to_bits())
+                .collect::<Vec<u64>>())
+        });
+        methods.add_method(
+            "read_black_box",
+            |_lua, lua_handle, (id, key): (u64, Option<String>)| {
+                let dropped = lua_handle
+                    .handle
+                    .nearby_black_boxes
+                    .iter()
+                    .find(|(entity, _)| entity.to_bits() == id)
+                    .map(|(_, data)| *data);
+                Ok(match (dropped, key) {
+                    (Some(data), Some(key)) => black_box::read_dropped(data, &key),
+                    (Some(data), None) => data.clone(),
+                    (None, _) => DataValue::Nil,
+                })
+            },
+        );
+        methods.add_method("inventory", |lua, lua_handle, ()| {
+            if let Some(inventory) = &lua_handle.handle.inventory {
+                let stacks = inventory
+                    .stacks
+                    .iter()
+                    .map(|stack| {
+                        let table = lua.create_table()?;
+                        table.set("item", stack.item.as_str())?;
+                        table.set("count", stack.count)?;
+                        Ok(table)
+                    })
+                    .collect::<LuaResult<Vec<LuaTable>>>()?;
+                Ok(LuaValue::Table(lua.create_sequence_from(stacks)?))
+            } else {
+                Ok(LuaValue::Nil)
+            }
+        });
+        methods.add_method("count_item", |_lua, lua_handle, item: String| {
+            Ok(lua_handle
+                .handle
+                .inventory
+                .as_ref()
+                .map_or(0, |inventory| inventory.count(&item)))
+        });
+        // Transfers are only queued here, their outcome is known from `last_transfer` on the
+        // next tick
+        methods.add_method_mut(
+            "transfer_items",
+            |_lua, lua_handle, (target, item, count): (u64, String, u32)| {
+                let source = lua_handle.handle.entity;
+                match &mut lua_handle.handle.inventory {
+                    Some(_) if target == source.to_bits() => {
+                        Ok((false, Some("can't transfer items to itself".to_string())))
+                    }
+                    Some(inventory) => {
+                        inventory.pending_transfers.push(ItemTransfer {
+                            target: Entity::from_bits(target),
+                            item,
+                            count,
+                        });
+                        Ok((true, None))
+                    }
+                    None => Ok((false, Some("unit has no inventory".to_string()))),
+                }
+            },
+        );
+        methods.add_method("last_transfer", |_lua, lua_handle, ()| {
+            match lua_handle
+                .handle
+                .inventory
+                .as_ref()
+                .and_then(|inventory| inventory.last_transfer.as_ref())
+            {
+                Some(Ok(moved)) => Ok((Some(*moved), None)),
+                Some(Err(e)) => Ok((None, Some(e.to_string()))),
+                None => Ok((None, None)),
+            }
+        });
+        methods.add_method(
+            "read_item_data",
+            |_lua, lua_handle, (slot, key): (usize, Option<String>)| {
+                let handle = &lua_handle.handle;
+                let result = match &handle.inventory {
+                    Some(inventory) => inventory.read_item_data(
+                        handle.prototypes,
+                        slot.wrapping_sub(1),
+                        key.as_deref(),
+                    ),
+                    None => return Ok((DataValue::Nil, Some("unit has no inventory".to_string()))),
+                };
+                match result {
+                    Ok(payload) => Ok((payload, None)),
+                    Err(e) => {
+                        log_item_data_error(handle.entity, slot, &e);
+                        Ok((DataValue::Nil, Some(e.to_string())))
+                    }
+                }
+            },
+        );
+        methods.add_method_mut(
+            "write_item_data",
+            |_lua, lua_handle, (slot, payload, key): (usize, DataValue, Option<String>)| {
+                let handle = &mut lua_handle.handle;
+                let result = match &mut handle.inventory {
+                    Some(inventory) => inventory.write_item_data(
+                        handle.prototypes,
+                        slot.wrapping_sub(1),
+                        payload,
+                        key.as_deref(),
+                    ),
+                    None => return Ok((false, Some("unit has no inventory".to_string()))),
+                };
+                match result {
+                    Ok(()) => Ok((true, None)),
+                    Err(e) => {
+                        log_item_data_error(handle.entity, slot, &e);
+                        Ok((false, Some(e.to_string())))
+                    }
+                }
+            },
+        );
+        methods.add_method_mut(
+            "set_item_key",
+            |_lua, lua_handle, (slot, new_key, key): (usize, Option<String>, Option<String>)| {
+                let handle = &mut lua_handle.handle;
+                let result = match &mut handle.inventory {
+                    Some(inventory) => inventory.set_item_key(
+                        handle.prototypes,
+                        slot.wrapping_sub(1),
+                        new_key,
+                        key.as_deref(),
+                    ),
+                    None => return Ok((false, Some("unit has no inventory".to_string()))),
+                };
+                match result {
+                    Ok(()) => Ok((true, None)),
+                    Err(e) => {
+                        log_item_data_error(handle.entity, slot, &e);
+                        Ok((false, Some(e.to_string())))
+                    }
+                }
+            },
+        );
+        methods.add_method_mut("scan_pickup_area", |lua, lua_handle, center: (f32, f32)| {
+            let handle = &mut lua_handle.handle;
+            let manipulator = match &mut handle.manipulator {
+                Some(manipulator) => manipulator,
+                None => return Ok((LuaValue::Nil, Some("unit has no manipulator".to_string()))),
+            };
+            match manipulator.scan(Vec2::from(center), &handle.nearby_items) {
+                Ok(found) => {
+                    let items = found
+                        .into_iter()
+                        .map(|(entity, stack)| {
+                            let table = lua.create_table()?;
+                            table.set("id", entity.to_bits())?;
+                            table.set("item", stack.item.as_str())?;
+                            table.set("count", stack.count)?;
+                            Ok(table)
+                        })
+                        .collect::<LuaResult<Vec<LuaTable>>>()?;
+                    Ok((LuaValue::Table(lua.create_sequence_from(items)?), None))
+                }
+                Err(e) => Ok((LuaValue::Nil, Some(e.to_string()))),
+            }
+        });
+        // Nothing else damages units yet, so this is how a unit ends up dropping its black box
+        methods.add_method_mut("self_destruct", |_lua, lua_handle, ()| {
+            if let Some(health) = &mut lua_handle.handle.health {
+                health.0 = 0.0;
+            }
+            Ok(())
+        });
+        methods.add_method_mut("pick_up", |_lua, lua_handle, id: u64| {
+            if let Some(manipulator) = &mut lua_handle.handle.manipulator {
+                match manipulator.pick_up(Entity::from_bits(id)) {
+                    Ok(()) => Ok((true, None)),
+                    Err(e) => Ok((false, Some(e.to_string()))),
+                }
+            } else {
+                Ok((false, Some("unit has no manipulator".to_string())))
+            }
+        });
+        methods.add_method("last_pickup", |_lua, lua_handle, ()| {
+            match lua_handle
+                .handle
+                .manipulator
+                .as_ref()
+                .and_then(|manipulator| manipulator.last_pickup.as_ref())
+            {
+                Some(Ok(picked_up)) => Ok((Some(*picked_up), None)),
+                Some(Err(e)) => Ok((None, Some(e.to_string()))),
+                None => Ok((None, None)),
+            }
         })
     }
 
     fn add_fields<'lua, F: LuaUserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("id", |_lua, lua_handle| {
+            Ok(lua_handle.handle.entity.to_bits())
+        });
         fields.add_field_method_get("time_since_start", |_lua, lua_handle| {
             Ok(lua_handle.handle.clock.0.elapsed_secs())
         });
@@ -148,10 +499,55 @@ impl LuaUserData for LuaUnitHandle<'_> {
             table.set("rotation", rotation_degrees)?;
             Ok(table)
         });
+        fields.add_field_method_get("radio", |lua, lua_handle| {
+            if let Some(radio) = &lua_handle.handle.radio {
+                let table = lua.create_table()?;
+                table.set("range", radio.range)?;
+                table.set("bandwidth", radio.bandwidth)?;
+                table.set("bandwidth_available", radio.bandwidth_available())?;
+                table.set("max_message_size", radio.max_message_size)?;
+                table.set("mailbox_size", radio.mailbox_size)?;
+                table.set("messages", radio.mailbox.len())?;
+                Ok(LuaValue::Table(table))
+            } else {
+                Ok(LuaValue::Nil)
+            }
+        });
+        fields.add_field_method_get("black_box", |lua, lua_handle| {
+            if let Some(black_box) = &lua_handle.handle.black_box {
+                let table = lua.create_table()?;
+                table.set("capacity", black_box.capacity)?;
+                table.set("used", black_box.used())?;
+                Ok(LuaValue::Table(table))
+            } else {
+                Ok(LuaValue::Nil)
+            }
+        });
+        fields.add_field_method_get("inventory_slots", |_lua, lua_handle| {
+            Ok(lua_handle
+                .handle
+                .inventory
+                .as_ref()
+                .map(|inventory| inventory.slots))
+        });
+        fields.add_field_method_get("manipulator", |lua, lua_handle| {
+            if let Some(manipulator) = &lua_handle.handle.manipulator {
+                let table = lua.create_table()?;
+                table.set("reach", manipulator.reach)?;
+                table.set("speed", manipulator.speed)?;
+                table.set("is_busy", manipulator.cooldown > 0.0)?;
+                Ok(LuaValue::Table(table))
+            } else {
+                Ok(LuaValue::Nil)
+            }
+        });
         fields.add_field_method_get("movement", |lua, lua_handle| {
             if let Some(movement) = &lua_handle.handle.movement {
                 let movement_type = movement.movement_type.as_ref();
-                let speed = movement.speed;
+                let speed = match movement.movement_type {
+                    MovementType::AcceleratedSteering => movement.current_speed,
+                    _ => movement.speed,
+                };
                 let max_speed = movement.max_speed;
                 let max_speed_backwards = movement.max_speed_backwards;
                 let acceleration = movement.acceleration;
@@ -1,5 +1,12 @@
 //! Implements loader for a custom asset type.
 
+use crate::{
+    data_value::storage::DataStorage,
+    items::{ItemStack, ItemTransfer, TransferError},
+    manipulator::ManipulatorError,
+    radio::RadioMessage,
+    PrototypesHandle,
+};
 use bevy::{
     asset::{AssetLoader, LoadContext, LoadedAsset},
     prelude::*,
@@ -7,28 +14,79 @@ use bevy::{
     utils::{BoxedFuture, HashMap},
 };
 use blake3::Hash;
+use schemars::JsonSchema;
 use scriplets_derive::{ComponentPrototype, Prototype};
-use serde::{Deserialize, Deserializer};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    any::TypeId,
+    collections::VecDeque,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
 use strum::AsRefStr;
 
+pub mod error;
+pub mod formats;
+pub mod localization;
+pub mod merge;
+pub mod migration;
+pub mod reference;
+pub mod registry;
+pub mod schema;
+pub mod validation;
+
+use error::json_error_message;
+pub use error::{PrototypesError, SourceLocation};
+use formats::Format;
+use localization::Localization;
+use merge::{merge_packs, resolve_inheritance, to_canonical_json, to_manifest};
+pub use merge::{read_pack, Manifest, PrototypesFragment};
+use reference::References;
+pub use reference::{PrototypeRef, Reference};
+use registry::CategoryMap;
+pub use registry::PrototypeCategory;
+pub use validation::Problem;
+use validation::Severity;
+
 pub trait Prototype<'de>: Deserialize<'de> {
+    /// Name of the category in prototype files
+    fn category() -> &'static str;
     fn name(&self) -> &str;
-    fn from_pt<'a, 'b>(prototypes_table: &'a Prototypes, name: &'b str) -> Option<&'a Self>;
+    /// References to other prototypes in the fields of the prototype. The derive collects them
+    /// from every field whose type mentions `PrototypeRef`.
+    fn references(&self) -> Vec<Reference<'_>> {
+        Vec::new()
+    }
+    fn from_pt<'a>(prototypes_table: &'a Prototypes, name: &str) -> Option<&'a Self>
+    where
+        Self: Sized + 'static,
+    {
+        prototypes_table.get(name)
+    }
 }
 
 pub trait ComponentPrototype<'de, T: Component = Self>: Prototype<'de> {
     fn to_component(&self) -> T;
-    fn component_from_pt(prototypes_table: &Prototypes, name: &str) -> Option<T> {
+    /// Update a component made from an earlier version of the prototype, keeping its state.
+    fn apply_to(&self, component: &mut T);
+    /// Name of the prototype the component was made from.
+    fn prototype_name(component: &T) -> &str;
+    fn component_from_pt(prototypes_table: &Prototypes, name: &str) -> Option<T>
+    where
+        Self: Sized + 'static,
+    {
         Self::from_pt(prototypes_table, name).map(Self::to_component)
     }
 }
 
-#[derive(Component, Prototype, ComponentPrototype, Deserialize, Clone)]
+#[derive(Prototype, ComponentPrototype, Deserialize, JsonSchema, Clone)]
 #[prot_category(movement)]
-pub struct Movement {
+#[prot_component(Movement)]
+#[prot_default(current_speed, input_move, input_rotation, hand_brake)]
+pub struct MovementPrototype {
     pub name: String,
     pub movement_type: MovementType,
-    // movement characteristics
     #[serde(default)]
     pub speed: f32, // tiles / second
     #[serde(default)]
@@ -45,16 +103,31 @@ pub struct Movement {
     pub rotation_speed: f32, // degrees / second
     #[serde(default)]
     pub rotation_offset: f32,
+}
+
+#[derive(Component, Clone)]
+pub struct Movement {
+    pub name: String,
+    pub movement_type: MovementType,
+    // movement characteristics
+    pub speed: f32,
+    pub max_speed: f32,
+    pub max_speed_backwards: Option<f32>,
+    pub acceleration: f32,
+    pub braking_acceleration: Option<f32>,
+    pub passive_deceleration: f32,
+    pub rotation_speed: f32,
+    pub rotation_offset: f32,
+    // state
+    /// Speed of accelerated steering movement
+    pub current_speed: f32,
     // input
-    #[serde(skip)]
     pub input_move: Vec2,
-    #[serde(skip)]
     pub input_rotation: f32,
-    #[serde(skip)]
     pub hand_brake: bool,
 }
 
-#[derive(Deserialize, Clone, AsRefStr)]
+#[derive(Deserialize, JsonSchema, Clone, AsRefStr)]
 #[serde(rename_all = "kebab-case")]
 #[strum(serialize_all = "kebab-case")]
 pub enum MovementType {
@@ -63,26 +136,343 @@ pub enum MovementType {
     Train,
 }
 
-#[derive(Deserialize, TypeUuid)]
+#[derive(Prototype, ComponentPrototype, Deserialize, JsonSchema, Clone)]
+#[prot_category(radio)]
+#[prot_component(Radio)]
+#[prot_default(bandwidth_used, outbox, mailbox)]
+pub struct RadioPrototype {
+    pub name: String,
+    pub range: f32,              // tiles
+    pub bandwidth: f32,          // bytes / second
+    pub max_message_size: usize, // bytes
+    pub mailbox_size: usize,     // messages
+}
+
+#[derive(Component, Clone)]
+pub struct Radio {
+    pub name: String,
+    pub range: f32,
+    pub bandwidth: f32,
+    pub max_message_size: usize,
+    pub mailbox_size: usize,
+    // state
+    /// Bytes sent within the last second, counted down as bandwidth is restored
+    pub bandwidth_used: f32,
+    pub outbox: Vec<RadioMessage>,
+    pub mailbox: VecDeque<RadioMessage>,
+}
+
+#[derive(Prototype, ComponentPrototype, Deserialize, JsonSchema, Clone)]
+#[prot_category(black_box)]
+#[prot_component(BlackBox)]
+#[prot_default(data)]
+pub struct BlackBoxPrototype {
+    pub name: String,
+    pub capacity: usize, // bytes
+    /// Item carrying data the black box is dropped as when its unit is destroyed
+    pub item: PrototypeRef<Item>,
+}
+
+#[derive(Component, Clone)]
+pub struct BlackBox {
+    pub name: String,
+    pub capacity: usize,
+    pub item: PrototypeRef<Item>,
+    // state
+    pub data: DataStorage,
+}
+
+#[derive(Component, Prototype, ComponentPrototype, Deserialize, JsonSchema, Clone)]
+#[prot_category(black_box_reader)]
+pub struct BlackBoxReader {
+    pub name: String,
+    pub range: f32, // tiles
+}
+
+#[derive(Prototype, Deserialize, JsonSchema, Clone)]
+#[prot_category(item)]
+pub struct Item {
+    pub name: String,
+    pub stack_size: u32,
+    /// Items that can carry data don't stack
+    #[serde(default)]
+    pub data_capacity: Option<usize>, // bytes
+}
+
+#[derive(Prototype, ComponentPrototype, Deserialize, JsonSchema, Clone)]
+#[prot_category(inventory)]
+#[prot_component(Inventory)]
+#[prot_default(stacks, pending_transfers, last_transfer)]
+pub struct InventoryPrototype {
+    pub name: String,
+    pub slots: usize,
+    #[serde(default)]
+    pub max_stack_size: Option<u32>,
+    #[serde(default)]
+    pub transfer_range: f32, // tiles
+}
+
+#[derive(Component, Clone)]
+pub struct Inventory {
+    pub name: String,
+    pub slots: usize,
+    pub max_stack_size: Option<u32>,
+    pub transfer_range: f32,
+    // state
+    pub stacks: Vec<ItemStack>,
+    pub pending_transfers: Vec<ItemTransfer>,
+    /// Outcome of the last processed transfer, how many items were moved
+    pub last_transfer: Option<Result<u32, TransferError>>,
+}
+
+#[derive(Prototype, ComponentPrototype, Deserialize, JsonSchema, Clone)]
+#[prot_category(manipulator)]
+#[prot_component(Manipulator)]
+#[prot_default(cooldown, scanned, pending_pickup, last_pickup)]
+pub struct ManipulatorPrototype {
+    pub name: String,
+    pub reach: f32, // tiles
+    pub area: PickupArea,
+    pub speed: f32, // pickups / second
+}
+
+#[derive(Component, Clone)]
+pub struct Manipulator {
+    pub name: String,
+    pub reach: f32,
+    pub area: PickupArea,
+    pub speed: f32,
+    // state
+    pub cooldown: f32,
+    pub scanned: Vec<Entity>,
+    pub pending_pickup: Option<Entity>,
+    /// Outcome of the last processed pickup, how many items were picked up
+    pub last_pickup: Option<Result<u32, ManipulatorError>>,
+}
+
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(tag = "shape", rename_all = "kebab-case")]
+pub enum PickupArea {
+    Circle { radius: f32 },
+    Rectangle { width: f32, height: f32 },
+}
+
+#[derive(Prototype, Deserialize, JsonSchema, Clone)]
+#[prot_category(processor)]
+pub struct Processor {
+    pub name: String,
+    pub memory_limit: usize, // bytes
+}
+
+/// Unit assembled from parts referenced by their prototype names.
+#[derive(Prototype, Deserialize, JsonSchema, Clone)]
+#[prot_category(unit)]
+pub struct UnitPrototype {
+    pub name: String,
+    pub collider: ColliderShape,
+    pub sprite: String,
+    pub health: f32,
+    #[serde(default)]
+    pub processor: Option<PrototypeRef<Processor>>,
+    #[serde(default)]
+    pub movement: Option<PrototypeRef<MovementPrototype>>,
+    #[serde(default)]
+    pub radio: Option<PrototypeRef<RadioPrototype>>,
+    #[serde(default)]
+    pub black_box: Option<PrototypeRef<BlackBoxPrototype>>,
+    #[serde(default)]
+    pub black_box_reader: Option<PrototypeRef<BlackBoxReader>>,
+    #[serde(default)]
+    pub inventory: Option<PrototypeRef<InventoryPrototype>>,
+    #[serde(default)]
+    pub manipulator: Option<PrototypeRef<ManipulatorPrototype>>,
+}
+
+#[derive(Deserialize, JsonSchema, Clone)]
+#[serde(tag = "shape", rename_all = "kebab-case")]
+pub enum ColliderShape {
+    Circle { radius: f32 },
+    Rectangle { width: f32, height: f32 },
+}
+
+#[derive(TypeUuid)]
 #[uuid = "a5034e09-33ec-4127-ad1e-36fe280e817a"]
 pub struct Prototypes {
-    #[serde(skip)]
     pub hash: Option<Hash>,
-    #[serde(deserialize_with = "hashmap_from_sequence")]
-    pub movement: HashMap<String, Movement>,
+    pub manifest: Manifest,
+    /// Merged prototypes in the canonical form `hash` is computed from
+    pub canonical_json: Arc<String>,
+    pub localization: Localization,
+    /// Prototypes of each registered category by the type of the prototypes
+    categories: HashMap<TypeId, CategoryMap>,
 }
 
-pub fn hashmap_from_sequence<'de, D: Deserializer<'de>, P: Prototype<'de>>(
-    deserializer: D,
-) -> Result<HashMap<String, P>, D::Error> {
-    Ok(Vec::<P>::deserialize(deserializer)?
-        .into_iter()
-        .map(|p| (p.name().to_string(), p))
-        .collect())
+impl Prototypes {
+    /// Merge packs of prototype fragments and validate the result, returning prototypes along
+    /// with warnings. All problems are reported at once, each pointing to the prototype that
+    /// caused it.
+    pub fn from_packs(
+        packs: &[Vec<&PrototypesFragment>],
+    ) -> Result<(Self, Vec<Problem>), PrototypesError> {
+        let (mut merged, mut problems) = merge_packs(packs);
+        resolve_inheritance(&mut merged, &mut problems);
+        merged.retain(|category, prototypes| {
+            if PrototypeCategory::find(category).is_some() {
+                return true;
+            }
+            for entry in prototypes.values() {
+                problems.push(Problem {
+                    severity: Severity::Warning,
+                    location: entry.location.clone(),
+                    category: category.clone(),
+                    name: entry.name.clone(),
+                    message: "unknown category, the prototype is ignored".to_string(),
+                });
+            }
+            false
+        });
+        for (category, prototypes) in &merged {
+            let registered =
+                PrototypeCategory::find(category).expect("unknown categories are removed");
+            for entry in prototypes.values() {
+                let fields = entry.fields.keys().cloned().collect();
+                let value = Value::Object(entry.fields.clone());
+                let mut problem = |severity, field: Option<&str>, message| {
+                    problems.push(Problem {
+                        severity,
+                        location: field
+                            .map_or(&entry.location, |field| entry.field_location(field))
+                            .clone(),
+                        category: category.clone(),
+                        name: entry.name.clone(),
+                        message,
+                    })
+                };
+                match registered.check(value, &fields, &merged) {
+                    Ok(found) => {
+                        for (severity, field, message) in found {
+                            problem(severity, field.as_deref(), message)
+                        }
+                    }
+                    Err(e) => problem(Severity::Error, None, json_error_message(&e)),
+                }
+            }
+        }
+        let localization = Localization::new(packs, &merged, &mut problems);
+        if problems.iter().any(Problem::is_error) {
+            return Err(PrototypesError::Invalid(problems));
+        }
+        let mut categories = HashMap::default();
+        for (category, prototypes) in &merged {
+            let registered =
+                PrototypeCategory::find(category).expect("unknown categories are removed");
+            let values = prototypes
+                .values()
+                .map(|entry| Value::Object(entry.fields.clone()))
+                .collect();
+            let map = registered
+                .deserialize(values)
+                .map_err(|e| PrototypesError::Incomplete(json_error_message(&e)))?;
+            categories.insert(registered.type_id(), map);
+        }
+        let manifest = to_manifest(&merged);
+        let canonical_json = to_canonical_json(&merged).to_string();
+        let prototypes = Self {
+            hash: Some(blake3::hash(canonical_json.as_bytes())),
+            manifest,
+            canonical_json: Arc::new(canonical_json),
+            localization,
+            categories,
+        };
+        Ok((prototypes, problems))
+    }
+
+    pub fn get<P: 'static>(&self, name: &str) -> Option<&P> {
+        self.category::<P>()?.get(name)
+    }
+
+    /// All prototypes of type `P` by name, `None` if there are none.
+    pub fn category<P: 'static>(&self) -> Option<&HashMap<String, P>> {
+        self.categories.get(&TypeId::of::<P>())?.downcast_ref()
+    }
+}
+
+/// Marks an entity with a component `T` made from a prototype that was removed while reloading.
+/// The component keeps the values of the removed prototype.
+#[derive(Component)]
+pub struct PrototypeRemoved<T: Send + Sync + 'static>(PhantomData<T>);
+
+impl<T: Send + Sync + 'static> Default for PrototypeRemoved<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
 }
 
-#[derive(Default)]
-pub struct PrototypesLoader;
+/// Apply reloaded prototypes to components made from them, keeping their state. Components whose
+/// prototype was removed are marked with `PrototypeRemoved`.
+pub fn reapply_prototypes<P: for<'de> ComponentPrototype<'de, C> + 'static, C: Component>(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Prototypes>>,
+    mut components: Query<(Entity, &mut C, Option<&PrototypeRemoved<C>>)>,
+    prototypes_handle: Res<PrototypesHandle>,
+    prototypes_assets: Res<Assets<Prototypes>>,
+) {
+    if !events.iter().any(
+        |event| matches!(event, AssetEvent::Modified { handle } if *handle == prototypes_handle.0),
+    ) {
+        return;
+    }
+    let prototypes = prototypes_assets.get(&prototypes_handle.0).unwrap();
+    for (entity, mut component, removed) in components.iter_mut() {
+        let name = P::prototype_name(&component).to_string();
+        match P::from_pt(prototypes, &name) {
+            Some(prototype) => {
+                prototype.apply_to(&mut component);
+                if removed.is_some() {
+                    commands.entity(entity).remove::<PrototypeRemoved<C>>();
+                }
+            }
+            None if removed.is_none() => {
+                warn!("Prototype `{}` used by {:?} was removed", name, entity);
+                commands
+                    .entity(entity)
+                    .insert(PrototypeRemoved::<C>::default());
+            }
+            None => {}
+        }
+    }
+}
+
+/// Errors that happened while loading prototypes, kept to be shown to the player.
+#[derive(Clone, Default)]
+pub struct PrototypesErrors(Arc<Mutex<Vec<PrototypesError>>>);
+
+impl PrototypesErrors {
+    pub fn push(&self, error: PrototypesError) {
+        self.0.lock().unwrap().push(error)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    pub fn take(&self) -> Vec<PrototypesError> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+pub struct PrototypesLoader {
+    errors: PrototypesErrors,
+}
+
+impl FromWorld for PrototypesLoader {
+    fn from_world(world: &mut World) -> Self {
+        let errors = world
+            .get_resource_or_insert_with(PrototypesErrors::default)
+            .clone();
+        Self { errors }
+    }
+}
 
 impl AssetLoader for PrototypesLoader {
     fn load<'a>(
@@ -91,16 +481,16 @@ impl AssetLoader for PrototypesLoader {
         load_context: &'a mut LoadContext,
     ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
         Box::pin(async move {
-            let hash = blake3::hash(bytes);
-            let mut prototypes: Prototypes = serde_json::from_slice(bytes).unwrap();
-            prototypes.hash = Some(hash);
-
-            load_context.set_default_asset(LoadedAsset::new(prototypes));
+            let fragment =
+                PrototypesFragment::from_bytes(bytes, load_context.path()).inspect_err(|e| {
+                    self.errors.push(e.clone());
+                })?;
+            load_context.set_default_asset(LoadedAsset::new(fragment));
             Ok(())
         })
     }
 
     fn extensions(&self) -> &[&str] {
-        &["json"]
+        &Format::EXTENSIONS
     }
 }
@@ -0,0 +1,109 @@
+//! Errors produced while loading prototypes, pointing to where in the source file they are.
+
+use super::validation::Problem;
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceLocation {
+    /// Location of the byte at `offset` in `source`.
+    pub fn at_offset(file: &Path, source: &[u8], offset: usize) -> Self {
+        let before = &source[..offset];
+        let line_start = before
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |newline| newline + 1);
+        Self {
+            file: file.to_path_buf(),
+            line: before.iter().filter(|&&b| b == b'\n').count() + 1,
+            column: offset - line_start + 1,
+        }
+    }
+
+    /// Location of `part`, which has to be a slice of `source`.
+    pub fn of_slice(file: &Path, source: &[u8], part: &str) -> Self {
+        let offset = part.as_ptr() as usize - source.as_ptr() as usize;
+        Self::at_offset(file, source, offset)
+    }
+
+    /// Turn a line and column relative to this location into an absolute location.
+    pub fn offset_by(&self, line: usize, column: usize) -> Self {
+        let (line, column) = match line {
+            0 => (self.line, self.column),
+            1 => (self.line, self.column + column.saturating_sub(1)),
+            _ => (self.line + line - 1, column),
+        };
+        Self {
+            file: self.file.clone(),
+            line,
+            column,
+        }
+    }
+
+    /// Location of a serde_json error that happened while parsing a slice starting at this
+    /// location.
+    pub fn of_json_error(&self, error: &serde_json::Error) -> Self {
+        self.offset_by(error.line(), error.column())
+    }
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum PrototypesError {
+    #[error("{location}: {message}")]
+    Syntax {
+        location: SourceLocation,
+        message: String,
+    },
+    #[error("{location}: invalid `{category}` category: {message}")]
+    InvalidCategory {
+        location: SourceLocation,
+        category: String,
+        message: String,
+    },
+    #[error(
+        "{location}: format version {version} is newer than the supported version {supported}"
+    )]
+    UnsupportedVersion {
+        location: SourceLocation,
+        version: u64,
+        supported: u32,
+    },
+    #[error("can't load prototype pack `{pack}`: {message}")]
+    Pack { pack: String, message: String },
+    #[error("prototypes are incomplete: {0}")]
+    Incomplete(String),
+    #[error("invalid prototypes:{}", display_problems(.0))]
+    Invalid(Vec<Problem>),
+}
+
+fn display_problems(problems: &[Problem]) -> String {
+    problems
+        .iter()
+        .map(|problem| format!("\n{}", problem))
+        .collect()
+}
+
+/// serde_json error message without the position, which is relative to the parsed slice.
+pub fn json_error_message(error: &serde_json::Error) -> String {
+    let message = error.to_string();
+    let position = format!(" at line {} column {}", error.line(), error.column());
+    match message.strip_suffix(&position) {
+        Some(message) => message.to_string(),
+        None => message,
+    }
+}
@@ -0,0 +1,152 @@
+//! Formats prototype files can be written in besides JSON, chosen by the file extension.
+//!
+//! Prototypes have the same structure in every format, values are converted to JSON values and
+//! processed the same way after parsing. Parsers of RON and TOML don't keep positions of values,
+//! so prototypes are located by scanning the source for keys, see `Format::tokens`.
+
+use super::{error::json_error_message, PrototypesError, SourceLocation};
+use serde_json::Value;
+use std::{collections::BTreeMap, path::Path};
+
+/// Word or string in the source of a prototype file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    /// Byte offset of the token, strings start at the opening quote
+    pub offset: usize,
+    /// Text of the token, strings are without quotes and escapes aren't processed
+    pub text: &'a str,
+    /// Whether the token is followed by `:`, `=`, `.` or `]`, which is how keys are followed
+    pub is_key: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Ron,
+    Toml,
+}
+
+impl Format {
+    pub const EXTENSIONS: [&'static str; 3] = ["json", "ron", "toml"];
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Self::Json),
+            "ron" => Some(Self::Ron),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// Parse prototype categories.
+    pub fn parse(
+        self,
+        bytes: &[u8],
+        file: &Path,
+    ) -> Result<BTreeMap<String, Value>, PrototypesError> {
+        let source = std::str::from_utf8(bytes).map_err(|e| PrototypesError::Syntax {
+            location: SourceLocation::at_offset(file, bytes, e.valid_up_to()),
+            message: "invalid UTF-8".to_string(),
+        })?;
+        match self {
+            Self::Json => serde_json::from_str(source).map_err(|e| PrototypesError::Syntax {
+                location: SourceLocation::at_offset(file, bytes, 0).of_json_error(&e),
+                message: json_error_message(&e),
+            }),
+            Self::Ron => ron::from_str(source).map_err(|e| PrototypesError::Syntax {
+                location: SourceLocation {
+                    file: file.to_path_buf(),
+                    line: e.position.line,
+                    column: e.position.col,
+                },
+                message: e.code.to_string(),
+            }),
+            Self::Toml => toml::from_str(source).map_err(|e| {
+                let (line, column) = e.line_col().unwrap_or_default();
+                let message = e.to_string();
+                let position = format!(" at line {} column {}", line + 1, column + 1);
+                PrototypesError::Syntax {
+                    location: SourceLocation {
+                        file: file.to_path_buf(),
+                        line: line + 1,
+                        column: column + 1,
+                    },
+                    message: message
+                        .strip_suffix(&position)
+                        .map_or(message.clone(), String::from),
+                }
+            }),
+        }
+    }
+
+    /// Split the source into words and strings, skipping comments. It's only a scanner, not a
+    /// parser: values can look like keys, strings with escapes don't match their value and raw
+    /// strings of RON aren't recognized, so locations found with the tokens are approximate.
+    pub fn tokens(self, source: &str) -> Vec<Token<'_>> {
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while let Some(c) = source[i..].chars().next() {
+            let rest = &source[i..];
+            if (rest.starts_with("//") && self == Self::Ron) || (c == '#' && self == Self::Toml) {
+                i += rest.find('\n').unwrap_or(rest.len());
+            } else if rest.starts_with("/*") && self == Self::Ron {
+                i += rest[2..].find("*/").map_or(rest.len(), |end| end + 4);
+            } else if c == '"' || c == '\'' {
+                let quote = ["\"\"\"", "'''"]
+                    .into_iter()
+                    .find(|quote| rest.starts_with(quote) && self == Self::Toml)
+                    .unwrap_or(&rest[..1]);
+                // Literal strings of TOML have no escapes
+                let escapes = c == '"' || self == Self::Ron;
+                let body = &rest[quote.len()..];
+                let end = closing_quote(body, quote, escapes);
+                let offset = i;
+                i = (i + quote.len() * 2 + end).min(source.len());
+                tokens.push(Token {
+                    offset,
+                    text: &body[..end],
+                    is_key: is_followed_by_key_end(&source[i..]),
+                });
+            } else if c.is_alphanumeric() || c == '_' {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+                    .unwrap_or(rest.len());
+                tokens.push(Token {
+                    offset: i,
+                    text: &rest[..len],
+                    is_key: is_followed_by_key_end(&rest[len..]),
+                });
+                i += len;
+            } else {
+                i += c.len_utf8();
+            }
+        }
+        tokens
+    }
+}
+
+/// Offset of the first key at or after `from`.
+pub fn key_offset(tokens: &[Token], key: &str, from: usize) -> Option<usize> {
+    tokens
+        .iter()
+        .find(|token| token.offset >= from && token.is_key && token.text == key)
+        .map(|token| token.offset)
+}
+
+/// Offset of the quote closing a string, or the end of the source if the string isn't closed.
+fn closing_quote(body: &str, quote: &str, escapes: bool) -> usize {
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if escapes && c == '\\' {
+            chars.next();
+        } else if body[i..].starts_with(quote) {
+            return i;
+        }
+    }
+    body.len()
+}
+
+fn is_followed_by_key_end(rest: &str) -> bool {
+    rest.trim_start_matches(&[' ', '\t'][..])
+        .starts_with(&[':', '=', '.', ']'][..])
+}
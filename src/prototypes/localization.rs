@@ -0,0 +1,196 @@
+//! Localized display names and descriptions of prototypes.
+//!
+//! A prototype can set `"display_name"` and `"description"` to keys of per-language string
+//! tables. String tables are files in a `locale` directory of a pack, named by the language, for
+//! example `locale/en.json`, each mapping keys to strings. Tables of later packs override keys of
+//! earlier ones. Strings missing in a language fall back to English.
+
+use super::{
+    formats::{key_offset, Format},
+    merge::MergedPrototypes,
+    validation::{Problem, Severity},
+    Prototype, PrototypesError, PrototypesFragment, SourceLocation,
+};
+use serde_json::Value;
+use std::{collections::BTreeMap, path::Path};
+
+pub const FALLBACK_LANGUAGE: &str = "en";
+const LOCALE_DIRECTORY: &str = "locale";
+
+/// Keys of strings by language.
+pub type StringTables = BTreeMap<String, BTreeMap<String, String>>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DisplayKeys {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Localization {
+    tables: StringTables,
+    /// Display keys by category and prototype name
+    keys: BTreeMap<String, BTreeMap<String, DisplayKeys>>,
+}
+
+impl Localization {
+    /// Merge string tables of all packs and collect display keys of the merged prototypes,
+    /// warning about keys that are missing in some language.
+    pub fn new(
+        packs: &[Vec<&PrototypesFragment>],
+        merged: &MergedPrototypes,
+        problems: &mut Vec<Problem>,
+    ) -> Self {
+        let mut tables = StringTables::new();
+        for pack in packs {
+            let mut fragments = pack.clone();
+            fragments.sort_by(|a, b| a.path.cmp(&b.path));
+            for fragment in fragments {
+                for (language, strings) in &fragment.strings {
+                    tables
+                        .entry(language.clone())
+                        .or_default()
+                        .extend(strings.clone());
+                }
+            }
+        }
+        let mut keys: BTreeMap<String, BTreeMap<String, DisplayKeys>> = BTreeMap::new();
+        for (category, prototypes) in merged {
+            for (name, entry) in prototypes {
+                let key = |field| {
+                    entry
+                        .fields
+                        .get(field)
+                        .and_then(Value::as_str)
+                        .map(String::from)
+                };
+                let display_keys = DisplayKeys {
+                    name: key("display_name"),
+                    description: key("description"),
+                };
+                let mut warning = |field, message| {
+                    problems.push(Problem {
+                        severity: Severity::Warning,
+                        location: entry.field_location(field).clone(),
+                        category: category.clone(),
+                        name: Some(name.clone()),
+                        message,
+                    })
+                };
+                for (field, key) in [
+                    ("display_name", &display_keys.name),
+                    ("description", &display_keys.description),
+                ] {
+                    let key = match key {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    if !contains(&tables, FALLBACK_LANGUAGE, key) {
+                        warning(
+                            field,
+                            format!("`{}` key `{}` has no English string", field, key),
+                        );
+                    }
+                    for language in tables.keys().filter(|l| *l != FALLBACK_LANGUAGE) {
+                        if !contains(&tables, language, key) {
+                            warning(
+                                field,
+                                format!(
+                                    "`{}` key `{}` has no string in `{}`, English is used instead",
+                                    field, key, language
+                                ),
+                            );
+                        }
+                    }
+                }
+                if display_keys != DisplayKeys::default() {
+                    keys.entry(category.clone())
+                        .or_default()
+                        .insert(name.clone(), display_keys);
+                }
+            }
+        }
+        Self { tables, keys }
+    }
+
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.tables.keys().map(String::as_str)
+    }
+
+    /// String of the key in the language, or in English if the language doesn't have it.
+    pub fn text(&self, language: &str, key: &str) -> Option<&str> {
+        [language, FALLBACK_LANGUAGE]
+            .into_iter()
+            .find_map(|language| self.tables.get(language)?.get(key))
+            .map(String::as_str)
+    }
+
+    pub fn display_keys(&self, category: &str, name: &str) -> Option<&DisplayKeys> {
+        self.keys.get(category)?.get(name)
+    }
+
+    /// Display name of the prototype in the language, its internal name if it doesn't have one.
+    pub fn display_name<'a, P: Prototype<'static>>(
+        &'a self,
+        prototype: &'a P,
+        language: &str,
+    ) -> &'a str {
+        self.display_keys(P::category(), prototype.name())
+            .and_then(|keys| keys.name.as_ref())
+            .and_then(|key| self.text(language, key))
+            .unwrap_or_else(|| prototype.name())
+    }
+
+    pub fn description<P: Prototype<'static>>(
+        &self,
+        prototype: &P,
+        language: &str,
+    ) -> Option<&str> {
+        let key = self
+            .display_keys(P::category(), prototype.name())?
+            .description
+            .as_ref()?;
+        self.text(language, key)
+    }
+}
+
+fn contains(tables: &StringTables, language: &str, key: &str) -> bool {
+    tables
+        .get(language)
+        .is_some_and(|strings| strings.contains_key(key))
+}
+
+/// Language of a string table file, `None` if the file has prototypes instead.
+pub fn string_table_language(file: &Path) -> Option<&str> {
+    let directory = file.parent()?.file_name()?;
+    if directory != LOCALE_DIRECTORY {
+        return None;
+    }
+    file.file_stem()?.to_str()
+}
+
+/// Parse a string table in the format given by the file's extension.
+pub fn parse_string_table(
+    bytes: &[u8],
+    file: &Path,
+) -> Result<BTreeMap<String, String>, PrototypesError> {
+    let format = Format::from_path(file).unwrap_or(Format::Json);
+    let table = format.parse(bytes, file)?;
+    // Parsing succeeded, so the source is valid UTF-8
+    let tokens = format.tokens(std::str::from_utf8(bytes).unwrap_or_default());
+    table
+        .into_iter()
+        .map(|(key, value)| match value {
+            Value::String(string) => Ok((key, string)),
+            _ => Err(PrototypesError::Syntax {
+                // Located by the key, or at the start if it can't be found
+                location: SourceLocation::at_offset(
+                    file,
+                    bytes,
+                    key_offset(&tokens, &key, 0).unwrap_or(0),
+                ),
+                message: format!("`{}` has to be a string", key),
+            }),
+        })
+        .collect()
+}
@@ -0,0 +1,475 @@
+//! Assembling prototypes from fragments spread over multiple files and packs.
+//!
+//! A pack is a directory tree of fragment files, each having the same structure as a complete
+//! prototypes file. Packs are applied in order: a prototype of a later pack overrides the one
+//! with the same name, and a prototype with `"remove": true` removes it.
+//!
+//! Files written for older versions of the format are upgraded first, see the `migration` module.
+//! Packs can also contain string tables for localization, see the `localization` module.
+//!
+//! After merging, a prototype with `"parent": "name"` inherits all fields it doesn't set from
+//! another prototype of the same category. Prototypes with `"abstract": true` are only templates
+//! to inherit from and don't end up in the result.
+
+use super::{
+    error::json_error_message,
+    formats::{key_offset, Format, Token},
+    localization::{parse_string_table, string_table_language, StringTables},
+    migration::{
+        migrate, parse_version, FieldLocations, RawCategories, RawPrototype, FORMAT_VERSION,
+        VERSION_KEY,
+    },
+    validation::{Problem, Severity},
+    PrototypesError, SourceLocation,
+};
+use bevy::reflect::TypeUuid;
+use serde::{Deserialize, Serialize};
+use serde_json::{value::RawValue, Map, Value};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Prototypes or a string table defined in a single file.
+#[derive(Debug, TypeUuid)]
+#[uuid = "1c4a3a5e-7a0e-4b5e-9f55-3c2b0f6e8d41"]
+pub struct PrototypesFragment {
+    pub path: PathBuf,
+    pub categories: BTreeMap<String, Vec<FragmentEntry>>,
+    pub strings: StringTables,
+}
+
+#[derive(Debug, Clone)]
+pub struct FragmentEntry {
+    pub location: SourceLocation,
+    pub name: Option<String>,
+    pub fields: Map<String, Value>,
+    /// Locations of the fields, including inherited ones once inheritance is resolved
+    pub field_locations: FieldLocations,
+    /// Removes the prototype with the same name defined by an earlier pack
+    pub remove: bool,
+    pub parent: Option<String>,
+    pub is_abstract: bool,
+}
+
+/// Prototypes of all packs merged together, by category and name.
+pub type MergedPrototypes = BTreeMap<String, BTreeMap<String, FragmentEntry>>;
+
+/// Hashes of individual prototypes by category and name, used to tell how two sets of prototypes
+/// differ.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest(pub BTreeMap<String, BTreeMap<String, String>>);
+
+impl FragmentEntry {
+    /// Entry of a prototype with its fields, taking out the ones that control merging.
+    pub fn new(prototype: RawPrototype) -> Result<Self, String> {
+        let RawPrototype {
+            location,
+            field_locations,
+            mut fields,
+        } = prototype;
+        let remove = matches!(fields.remove("remove"), Some(Value::Bool(true)));
+        let is_abstract = matches!(fields.remove("abstract"), Some(Value::Bool(true)));
+        let parent = match fields.remove("parent") {
+            None => None,
+            Some(Value::String(parent)) => Some(parent),
+            Some(_) => return Err("`parent` has to be a name of a prototype".to_string()),
+        };
+        Ok(Self {
+            location,
+            name: fields.get("name").and_then(Value::as_str).map(String::from),
+            fields,
+            field_locations,
+            remove,
+            parent,
+            is_abstract,
+        })
+    }
+
+    /// Location of a field, `field.nested` for fields of nested values. Fields without a known
+    /// location are located at the prototype.
+    pub fn field_location(&self, field: &str) -> &SourceLocation {
+        let field = field.split('.').next().unwrap_or(field);
+        self.field_locations.get(field).unwrap_or(&self.location)
+    }
+}
+
+impl PrototypesFragment {
+    /// Parse a fragment in the format given by the file's extension.
+    pub fn from_bytes(bytes: &[u8], file: &Path) -> Result<Self, PrototypesError> {
+        if let Some(language) = string_table_language(file) {
+            let strings = parse_string_table(bytes, file)?;
+            return Ok(Self {
+                path: file.to_path_buf(),
+                categories: BTreeMap::new(),
+                strings: [(language.to_string(), strings)].into(),
+            });
+        }
+        match Format::from_path(file) {
+            Some(Format::Json) | None => Self::from_json(bytes, file),
+            Some(format) => {
+                let categories = format.parse(bytes, file)?;
+                Self::from_categories(bytes, file, format, categories)
+            }
+        }
+    }
+
+    pub fn from_json(bytes: &[u8], file: &Path) -> Result<Self, PrototypesError> {
+        let start = SourceLocation::at_offset(file, bytes, 0);
+        let mut raw_categories: BTreeMap<String, &RawValue> = serde_json::from_slice(bytes)
+            .map_err(|e| PrototypesError::Syntax {
+                location: start.of_json_error(&e),
+                message: json_error_message(&e),
+            })?;
+        let version = match raw_categories.remove(VERSION_KEY) {
+            Some(raw_version) => parse_version(
+                serde_json::from_str(raw_version.get()).ok().as_ref(),
+                SourceLocation::of_slice(file, bytes, raw_version.get()),
+            )?,
+            None => parse_version(None, start)?,
+        };
+        let mut categories = BTreeMap::new();
+        for (category, raw_category) in raw_categories {
+            let category_location = SourceLocation::of_slice(file, bytes, raw_category.get());
+            let invalid_category =
+                |location: SourceLocation, message| PrototypesError::InvalidCategory {
+                    location,
+                    category: category.clone(),
+                    message,
+                };
+            let raw_prototypes: Vec<&RawValue> =
+                serde_json::from_str(raw_category.get()).map_err(|e| {
+                    invalid_category(category_location.of_json_error(&e), json_error_message(&e))
+                })?;
+            let mut prototypes = Vec::new();
+            for raw_prototype in raw_prototypes {
+                let location = SourceLocation::of_slice(file, bytes, raw_prototype.get());
+                let raw_fields: BTreeMap<String, &RawValue> =
+                    serde_json::from_str(raw_prototype.get()).map_err(|e| {
+                        invalid_category(location.of_json_error(&e), json_error_message(&e))
+                    })?;
+                let mut fields = Map::new();
+                let mut field_locations = BTreeMap::new();
+                for (field, raw_value) in raw_fields {
+                    let field_location = SourceLocation::of_slice(file, bytes, raw_value.get());
+                    let value = serde_json::from_str(raw_value.get()).map_err(|e| {
+                        invalid_category(field_location.of_json_error(&e), json_error_message(&e))
+                    })?;
+                    fields.insert(field.clone(), value);
+                    field_locations.insert(field, field_location);
+                }
+                prototypes.push(RawPrototype {
+                    location,
+                    field_locations,
+                    fields,
+                });
+            }
+            categories.insert(category, prototypes);
+        }
+        Ok(Self {
+            path: file.to_path_buf(),
+            categories: to_entries(migrate(version, categories))?,
+            strings: StringTables::new(),
+        })
+    }
+
+    /// Build a fragment from categories parsed from a format other than JSON. These formats
+    /// don't keep positions of values, so categories are located by their keys and prototypes by
+    /// their `name` keys in the tokens of the source, fields by their keys near the `name` key.
+    /// Locations are approximate, a value that looks like the key can be found instead of it.
+    fn from_categories(
+        bytes: &[u8],
+        file: &Path,
+        format: Format,
+        mut categories: BTreeMap<String, Value>,
+    ) -> Result<Self, PrototypesError> {
+        // Parsing succeeded, so the source is valid UTF-8
+        let source = std::str::from_utf8(bytes).unwrap_or_default();
+        let tokens = format.tokens(source);
+        let version_offset = key_offset(&tokens, VERSION_KEY, 0).unwrap_or(0);
+        let version = parse_version(
+            categories.remove(VERSION_KEY).as_ref(),
+            SourceLocation::at_offset(file, bytes, version_offset),
+        )?;
+        let mut raw_categories = BTreeMap::new();
+        for (category, value) in categories {
+            let category_offset = key_offset(&tokens, &category, 0).unwrap_or(0);
+            let category_location = SourceLocation::at_offset(file, bytes, category_offset);
+            let invalid_category = |location, message: &str| PrototypesError::InvalidCategory {
+                location,
+                category: category.clone(),
+                message: message.to_string(),
+            };
+            let prototypes = match value {
+                Value::Array(prototypes) => prototypes,
+                _ => return Err(invalid_category(category_location, "expected a sequence")),
+            };
+            let mut search_from = category_offset;
+            let mut previous = category_offset;
+            let mut located = Vec::new();
+            for prototype in prototypes {
+                let fields = match prototype {
+                    Value::Object(fields) => fields,
+                    _ => {
+                        return Err(invalid_category(
+                            category_location.clone(),
+                            "expected a prototype",
+                        ))
+                    }
+                };
+                if let Some(offset) = fields
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .and_then(|name| name_offset(&tokens, name, search_from))
+                {
+                    previous = search_from;
+                    search_from = offset;
+                }
+                let location = SourceLocation::at_offset(file, bytes, search_from);
+                // Fields usually follow the name, the ones before it are after the previous name
+                let field_locations = fields
+                    .keys()
+                    .filter_map(|field| {
+                        let offset = key_offset(&tokens, field, search_from)
+                            .or_else(|| key_offset(&tokens, field, previous))?;
+                        Some((
+                            field.clone(),
+                            SourceLocation::at_offset(file, bytes, offset),
+                        ))
+                    })
+                    .collect();
+                located.push(RawPrototype {
+                    location,
+                    field_locations,
+                    fields,
+                });
+            }
+            raw_categories.insert(category, located);
+        }
+        Ok(Self {
+            path: file.to_path_buf(),
+            categories: to_entries(migrate(version, raw_categories))?,
+            strings: StringTables::new(),
+        })
+    }
+}
+
+/// Entries of prototypes, after they are migrated.
+fn to_entries(
+    categories: RawCategories,
+) -> Result<BTreeMap<String, Vec<FragmentEntry>>, PrototypesError> {
+    categories
+        .into_iter()
+        .map(|(category, prototypes)| {
+            let entries = prototypes
+                .into_iter()
+                .map(|prototype| {
+                    let location = prototype.location.clone();
+                    FragmentEntry::new(prototype).map_err(|message| {
+                        PrototypesError::InvalidCategory {
+                            location,
+                            category: category.clone(),
+                            message,
+                        }
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            Ok((category, entries))
+        })
+        .collect()
+}
+
+/// Offset of the value of the first `name` key with the value `name` at or after `from`.
+fn name_offset(tokens: &[Token], name: &str, from: usize) -> Option<usize> {
+    tokens
+        .windows(2)
+        .find(|pair| {
+            pair[0].offset >= from
+                && pair[0].is_key
+                && pair[0].text == "name"
+                && pair[1].text == name
+        })
+        .map(|pair| pair[1].offset)
+}
+
+/// Read all prototype files in a directory tree as fragments of a single pack. The pack can also
+/// be a single file.
+pub fn read_pack(path: &Path) -> Result<Vec<PrototypesFragment>, PrototypesError> {
+    let mut files = Vec::new();
+    collect_files(path, &mut files).map_err(|e| PrototypesError::Pack {
+        pack: path.display().to_string(),
+        message: e.to_string(),
+    })?;
+    files
+        .into_iter()
+        .map(|file| {
+            let bytes = fs::read(&file).map_err(|e| PrototypesError::Pack {
+                pack: path.display().to_string(),
+                message: format!("can't read {}: {}", file.display(), e),
+            })?;
+            PrototypesFragment::from_bytes(&bytes, &file)
+        })
+        .collect()
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else if Format::from_path(&path).is_some() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Merge packs in order. Fragments of a pack are applied in order of their paths, defining a
+/// prototype twice within the same pack is an error.
+pub fn merge_packs(packs: &[Vec<&PrototypesFragment>]) -> (MergedPrototypes, Vec<Problem>) {
+    let mut merged = MergedPrototypes::new();
+    let mut problems = Vec::new();
+    for pack in packs {
+        let mut fragments = pack.clone();
+        fragments.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut defined_in_pack: BTreeMap<(&str, &str), &SourceLocation> = BTreeMap::new();
+        for fragment in fragments {
+            for (category, entries) in &fragment.categories {
+                let merged_category = merged.entry(category.clone()).or_default();
+                for entry in entries {
+                    let mut problem = |severity, message| {
+                        problems.push(Problem {
+                            severity,
+                            location: entry.location.clone(),
+                            category: category.clone(),
+                            name: entry.name.clone(),
+                            message,
+                        })
+                    };
+                    let name = match &entry.name {
+                        Some(name) => name,
+                        None => {
+                            problem(Severity::Error, "missing field `name`".to_string());
+                            continue;
+                        }
+                    };
+                    if let Some(first) = defined_in_pack.get(&(category.as_str(), name.as_str())) {
+                        problem(
+                            Severity::Error,
+                            format!("defined more than once, first definition is at {}", first),
+                        );
+                        continue;
+                    }
+                    defined_in_pack.insert((category.as_str(), name.as_str()), &entry.location);
+                    if !entry.remove {
+                        merged_category.insert(name.clone(), entry.clone());
+                    } else if merged_category.remove(name).is_none() {
+                        problem(
+                            Severity::Warning,
+                            "removes a prototype that isn't defined by earlier packs".to_string(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    (merged, problems)
+}
+
+/// Fill in fields inherited from parents and drop abstract prototypes. Prototypes with a missing
+/// parent or an inheritance cycle are reported and dropped as well.
+pub fn resolve_inheritance(merged: &mut MergedPrototypes, problems: &mut Vec<Problem>) {
+    for (category, prototypes) in merged.iter_mut() {
+        let mut resolved = BTreeMap::new();
+        for (name, entry) in prototypes.iter() {
+            match inherited_fields(prototypes, name) {
+                Ok((fields, field_locations)) => {
+                    if !entry.is_abstract {
+                        resolved.insert(
+                            name.clone(),
+                            FragmentEntry {
+                                fields,
+                                field_locations,
+                                ..entry.clone()
+                            },
+                        );
+                    }
+                }
+                Err(message) => problems.push(Problem {
+                    severity: Severity::Error,
+                    location: entry.location.clone(),
+                    category: category.clone(),
+                    name: Some(name.clone()),
+                    message,
+                }),
+            }
+        }
+        *prototypes = resolved;
+    }
+}
+
+/// Fields of the prototype combined with fields of all its ancestors, along with their locations.
+fn inherited_fields(
+    prototypes: &BTreeMap<String, FragmentEntry>,
+    name: &str,
+) -> Result<(Map<String, Value>, FieldLocations), String> {
+    let mut chain = vec![name];
+    let mut entry = &prototypes[name];
+    while let Some(parent) = &entry.parent {
+        if chain.contains(&parent.as_str()) {
+            chain.push(parent);
+            return Err(format!("inheritance cycle: {}", chain.join(" -> ")));
+        }
+        entry = prototypes
+            .get(parent)
+            .ok_or_else(|| format!("parent `{}` doesn't exist", parent))?;
+        chain.push(parent);
+    }
+    let mut fields = Map::new();
+    let mut field_locations = FieldLocations::new();
+    for ancestor in chain.into_iter().rev() {
+        fields.extend(prototypes[ancestor].fields.clone());
+        field_locations.extend(prototypes[ancestor].field_locations.clone());
+    }
+    Ok((fields, field_locations))
+}
+
+/// Merged prototypes as a single JSON value in the current format, with prototypes sorted by
+/// name. Equal prototypes always produce the same value, no matter how they were split into
+/// files.
+pub fn to_canonical_json(merged: &MergedPrototypes) -> Value {
+    let mut canonical: Map<_, _> = merged
+        .iter()
+        .map(|(category, prototypes)| {
+            let prototypes = prototypes
+                .values()
+                .map(|entry| Value::Object(entry.fields.clone()))
+                .collect();
+            (category.clone(), Value::Array(prototypes))
+        })
+        .collect();
+    canonical.insert(VERSION_KEY.to_string(), FORMAT_VERSION.into());
+    canonical.into()
+}
+
+pub fn to_manifest(merged: &MergedPrototypes) -> Manifest {
+    let manifest = merged
+        .iter()
+        .map(|(category, prototypes)| {
+            let hashes = prototypes
+                .iter()
+                .map(|(name, entry)| {
+                    let json = serde_json::to_vec(&entry.fields).expect("JSON values serialize");
+                    (name.clone(), blake3::hash(&json).to_hex().to_string())
+                })
+                .collect();
+            (category.clone(), hashes)
+        })
+        .collect();
+    Manifest(manifest)
+}
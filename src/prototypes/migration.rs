@@ -0,0 +1,89 @@
+//! Upgrading prototype files written for older versions of the format.
+//!
+//! Files state the version of the format they are written in with a top-level
+//! `"format_version"` key, files without it are version 1. Every prototype of an older file is
+//! passed through the migrations up to the current version before it's merged and deserialized,
+//! so mods keep working when the engine renames or restructures fields.
+
+use super::{PrototypesError, SourceLocation};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+pub const FORMAT_VERSION: u32 = 1;
+pub const VERSION_KEY: &str = "format_version";
+
+/// Locations of fields of a prototype by field name.
+pub type FieldLocations = BTreeMap<String, SourceLocation>;
+
+/// Prototype as it's written in a file.
+#[derive(Debug, Clone)]
+pub struct RawPrototype {
+    pub location: SourceLocation,
+    /// Locations of the fields of the prototype. Fields renamed by migrations lose theirs.
+    pub field_locations: FieldLocations,
+    pub fields: Map<String, Value>,
+}
+
+/// Prototypes of a file by category, as they are written.
+pub type RawCategories = BTreeMap<String, Vec<RawPrototype>>;
+
+/// Upgrades a prototype by one version. Gets the category and all fields of the prototype,
+/// including the ones that control merging, the category can be changed to move the prototype to
+/// another one.
+pub type Migration = fn(&mut String, &mut Map<String, Value>);
+
+/// Migration from version `n` to `n + 1` is at index `n - 1`. Version 1 is the first version of
+/// the format, so there are none yet.
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize - 1] = [];
+
+/// Version of a file from the value of its `format_version` key.
+pub fn parse_version(
+    value: Option<&Value>,
+    location: SourceLocation,
+) -> Result<u32, PrototypesError> {
+    let version = match value {
+        None => return Ok(1),
+        Some(value) => value.as_u64().filter(|&version| version > 0),
+    };
+    match version {
+        Some(version) if version <= FORMAT_VERSION as u64 => Ok(version as u32),
+        Some(version) => Err(PrototypesError::UnsupportedVersion {
+            location,
+            version,
+            supported: FORMAT_VERSION,
+        }),
+        None => Err(PrototypesError::Syntax {
+            location,
+            message: format!("`{}` has to be a positive integer", VERSION_KEY),
+        }),
+    }
+}
+
+/// Upgrade prototypes of a file written in `version` of the format to the current version.
+pub fn migrate(version: u32, categories: RawCategories) -> RawCategories {
+    migrate_with(&MIGRATIONS, version, categories)
+}
+
+/// Upgrade prototypes of a file written in `version` of the format with `migrations`, indexed like
+/// `MIGRATIONS`. `version` can be at most one past the last migration.
+pub fn migrate_with(
+    migrations: &[Migration],
+    version: u32,
+    categories: RawCategories,
+) -> RawCategories {
+    let migrations = &migrations[version as usize - 1..];
+    if migrations.is_empty() {
+        return categories;
+    }
+    let mut migrated = RawCategories::new();
+    for (category, prototypes) in categories {
+        for mut prototype in prototypes {
+            let mut category = category.clone();
+            for migration in migrations {
+                migration(&mut category, &mut prototype.fields);
+            }
+            migrated.entry(category).or_default().push(prototype);
+        }
+    }
+    migrated
+}
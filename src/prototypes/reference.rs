@@ -0,0 +1,118 @@
+//! References between prototypes.
+
+use super::{Prototype, Prototypes};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Deserialize, Deserializer};
+use std::{
+    fmt::{self, Debug},
+    marker::PhantomData,
+};
+
+/// Reference to a prototype found in a field of another one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference<'a> {
+    pub field: &'static str,
+    pub category: &'static str,
+    pub name: &'a str,
+}
+
+/// Types of prototype fields that can hold references, used by the `Prototype` derive.
+pub trait References {
+    fn collect<'a>(&'a self, field: &'static str, references: &mut Vec<Reference<'a>>);
+}
+
+impl<T: Prototype<'static>> References for PrototypeRef<T> {
+    fn collect<'a>(&'a self, field: &'static str, references: &mut Vec<Reference<'a>>) {
+        references.push(Reference {
+            field,
+            category: T::category(),
+            name: &self.name,
+        })
+    }
+}
+
+impl<R: References> References for Option<R> {
+    fn collect<'a>(&'a self, field: &'static str, references: &mut Vec<Reference<'a>>) {
+        if let Some(reference) = self {
+            reference.collect(field, references)
+        }
+    }
+}
+
+impl<R: References> References for Vec<R> {
+    fn collect<'a>(&'a self, field: &'static str, references: &mut Vec<Reference<'a>>) {
+        for reference in self {
+            reference.collect(field, references)
+        }
+    }
+}
+
+/// Name of a prototype of type `T`, written as a string in prototype files. References are
+/// checked when prototypes are loaded, so a reference resolves in the prototypes it was loaded
+/// with.
+pub struct PrototypeRef<T> {
+    name: String,
+    prototype: PhantomData<fn() -> T>,
+}
+
+impl<T> PrototypeRef<T> {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            prototype: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get<'a>(&self, prototypes: &'a Prototypes) -> Option<&'a T>
+    where
+        T: 'static,
+    {
+        prototypes.get(&self.name)
+    }
+}
+
+impl<T> Clone for PrototypeRef<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.name.clone())
+    }
+}
+
+impl<T> PartialEq for PrototypeRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl<T> Eq for PrototypeRef<T> {}
+
+impl<T> Debug for PrototypeRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PrototypeRef").field(&self.name).finish()
+    }
+}
+
+impl<'de, T> Deserialize<'de> for PrototypeRef<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::new)
+    }
+}
+
+impl<T: Prototype<'static>> JsonSchema for PrototypeRef<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        format!("PrototypeRef_{}", T::category())
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        let mut schema = String::json_schema(generator).into_object();
+        schema.metadata().description = Some(format!("Name of a {} prototype", T::category()));
+        schema.into()
+    }
+}
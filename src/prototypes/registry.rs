@@ -0,0 +1,112 @@
+//! Registry of prototype categories. Deriving `Prototype` registers the category of the type, so
+//! adding a category only takes a new prototype type.
+
+use super::{
+    merge::MergedPrototypes,
+    schema::category_schema,
+    validation::{Finding, Validate, Validator},
+    Prototype,
+};
+use bevy::utils::HashMap;
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde_json::Value;
+use std::{
+    any::{Any, TypeId},
+    collections::BTreeSet,
+};
+
+/// Prototypes of a single category, a `HashMap<String, P>` by name.
+pub type CategoryMap = Box<dyn Any + Send + Sync>;
+
+type Check = fn(Value, &BTreeSet<String>, &MergedPrototypes) -> CheckResult;
+type CheckResult = serde_json::Result<Vec<Finding>>;
+
+/// Category of prototypes of type `P`, registered with `inventory::submit!`. Generic prototypes
+/// aren't registered by the derive and have to be registered for each type argument by hand.
+pub struct PrototypeCategory {
+    name: fn() -> &'static str,
+    type_id: fn() -> TypeId,
+    check: Check,
+    deserialize: fn(Vec<Value>) -> serde_json::Result<CategoryMap>,
+    schema: fn(&mut SchemaGenerator) -> Schema,
+}
+
+inventory::collect!(PrototypeCategory);
+
+impl PrototypeCategory {
+    pub const fn new<P>() -> Self
+    where
+        P: for<'de> Prototype<'de> + Validate + JsonSchema + Send + Sync + 'static,
+    {
+        Self {
+            name: P::category,
+            type_id: TypeId::of::<P>,
+            check: check::<P>,
+            deserialize: deserialize::<P>,
+            schema: category_schema::<P>,
+        }
+    }
+
+    /// All registered categories, in no particular order.
+    pub fn all() -> impl Iterator<Item = &'static Self> {
+        inventory::iter::<Self>.into_iter()
+    }
+
+    pub fn find(name: &str) -> Option<&'static Self> {
+        Self::all().find(|category| category.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        (self.name)()
+    }
+
+    pub fn type_id(&self) -> TypeId {
+        (self.type_id)()
+    }
+
+    /// Deserialize and validate a single prototype, including its references. `fields` are the
+    /// fields present in the source.
+    pub fn check(
+        &self,
+        json: Value,
+        fields: &BTreeSet<String>,
+        known: &MergedPrototypes,
+    ) -> CheckResult {
+        (self.check)(json, fields, known)
+    }
+
+    /// Deserialize all prototypes of the category into a map by name.
+    pub fn deserialize(&self, prototypes: Vec<Value>) -> serde_json::Result<CategoryMap> {
+        (self.deserialize)(prototypes)
+    }
+
+    /// Schema of the category in prototype files.
+    pub fn schema(&self, generator: &mut SchemaGenerator) -> Schema {
+        (self.schema)(generator)
+    }
+}
+
+fn check<P: for<'de> Prototype<'de> + Validate>(
+    json: Value,
+    fields: &BTreeSet<String>,
+    known: &MergedPrototypes,
+) -> CheckResult {
+    let prototype: P = serde_json::from_value(json)?;
+    let mut validator = Validator::new(fields, known);
+    prototype.validate(&mut validator);
+    for reference in prototype.references() {
+        validator.reference(&reference);
+    }
+    Ok(validator.into_problems())
+}
+
+fn deserialize<P: for<'de> Prototype<'de> + Send + Sync + 'static>(
+    prototypes: Vec<Value>,
+) -> serde_json::Result<CategoryMap> {
+    let mut map = HashMap::<String, P>::default();
+    for prototype in prototypes {
+        let prototype: P = serde_json::from_value(prototype)?;
+        map.insert(prototype.name().to_string(), prototype);
+    }
+    Ok(Box::new(map))
+}
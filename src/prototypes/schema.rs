@@ -0,0 +1,86 @@
+//! JSON Schema of prototype files, for autocompletion and checking in editors.
+
+use super::{migration::VERSION_KEY, Prototype, PrototypeCategory};
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::{ArrayValidation, InstanceType, Metadata, RootSchema, Schema, SchemaObject},
+    JsonSchema,
+};
+
+/// Schema of a single prototype file. Since prototypes can inherit fields from a parent, only
+/// `name` is required.
+pub fn prototypes_schema() -> RootSchema {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let mut root = SchemaObject {
+        metadata: Some(Box::new(Metadata {
+            title: Some("Scriplets prototypes".to_string()),
+            ..Default::default()
+        })),
+        instance_type: Some(InstanceType::Object.into()),
+        ..Default::default()
+    };
+    root.object().properties.insert(
+        VERSION_KEY.to_string(),
+        described::<u32>(
+            &mut generator,
+            "Version of the format the file is written in",
+        ),
+    );
+    for category in PrototypeCategory::all() {
+        let schema = category.schema(&mut generator);
+        root.object()
+            .properties
+            .insert(category.name().to_string(), schema);
+    }
+    RootSchema {
+        meta_schema: generator.settings().meta_schema.clone(),
+        schema: root,
+        definitions: generator.take_definitions(),
+    }
+}
+
+/// Schema of a category, a sequence of prototypes that can also use the keys controlling merging.
+pub fn category_schema<P: Prototype<'static> + JsonSchema>(
+    generator: &mut SchemaGenerator,
+) -> Schema {
+    let mut prototype = P::json_schema(generator).into_object();
+    let object = prototype.object();
+    object.required = ["name".to_string()].into();
+    object.properties.extend([
+        (
+            "parent".to_string(),
+            described::<String>(generator, "Prototype to inherit unset fields from"),
+        ),
+        (
+            "abstract".to_string(),
+            described::<bool>(generator, "Only a template for other prototypes to inherit"),
+        ),
+        (
+            "remove".to_string(),
+            described::<bool>(generator, "Remove the prototype defined by an earlier pack"),
+        ),
+        (
+            "display_name".to_string(),
+            described::<String>(generator, "Key of the localized name shown to players"),
+        ),
+        (
+            "description".to_string(),
+            described::<String>(generator, "Key of the localized description"),
+        ),
+    ]);
+    let category = SchemaObject {
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(ArrayValidation {
+            items: Some(Schema::Object(prototype).into()),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+    category.into()
+}
+
+fn described<T: JsonSchema>(generator: &mut SchemaGenerator, description: &str) -> Schema {
+    let mut schema = generator.subschema_for::<T>().into_object();
+    schema.metadata().description = Some(description.to_string());
+    schema.into()
+}
@@ -0,0 +1,271 @@
+//! Checks that values of deserialized prototypes make sense.
+
+use super::{
+    merge::MergedPrototypes, BlackBoxPrototype, BlackBoxReader, ColliderShape, InventoryPrototype,
+    Item, ManipulatorPrototype, MovementPrototype, MovementType, PickupArea, Processor,
+    RadioPrototype, Reference, SourceLocation, UnitPrototype,
+};
+use std::{
+    collections::BTreeSet,
+    fmt::{self, Display},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    pub location: SourceLocation,
+    pub category: String,
+    pub name: Option<String>,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(
+            f,
+            "{}: {}: {} prototype ",
+            severity, self.location, self.category
+        )?;
+        match &self.name {
+            Some(name) => write!(f, "`{}`", name)?,
+            None => write!(f, "without a name")?,
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Problem found in a single prototype, with the field it's about if there is one. Fields of
+/// nested values are written as `field.nested`.
+pub type Finding = (Severity, Option<String>, String);
+
+/// Collects problems found in a single prototype.
+pub struct Validator<'a> {
+    fields: &'a BTreeSet<String>,
+    known: &'a MergedPrototypes,
+    problems: Vec<Finding>,
+}
+
+impl<'a> Validator<'a> {
+    /// `fields` are the fields that were present in the source data of the prototype, `known`
+    /// are all prototypes it can reference.
+    pub fn new(fields: &'a BTreeSet<String>, known: &'a MergedPrototypes) -> Self {
+        Self {
+            fields,
+            known,
+            problems: Vec::new(),
+        }
+    }
+
+    pub fn into_problems(self) -> Vec<Finding> {
+        self.problems
+    }
+
+    pub fn error(&mut self, message: String) {
+        self.problems.push((Severity::Error, None, message))
+    }
+
+    pub fn warning(&mut self, message: String) {
+        self.problems.push((Severity::Warning, None, message))
+    }
+
+    pub fn field_error(&mut self, field: &str, message: String) {
+        self.problems
+            .push((Severity::Error, Some(field.to_string()), message))
+    }
+
+    pub fn field_warning(&mut self, field: &str, message: String) {
+        self.problems
+            .push((Severity::Warning, Some(field.to_string()), message))
+    }
+
+    pub fn non_negative(&mut self, field: &str, value: f32) {
+        if value < 0.0 || value.is_nan() {
+            self.field_error(
+                field,
+                format!("`{}` can't be negative, got {}", field, value),
+            )
+        }
+    }
+
+    pub fn positive(&mut self, field: &str, value: f32) {
+        if value <= 0.0 || value.is_nan() {
+            self.field_error(
+                field,
+                format!("`{}` has to be greater than 0, got {}", field, value),
+            )
+        }
+    }
+
+    pub fn not_zero(&mut self, field: &str, value: usize) {
+        if value == 0 {
+            self.field_error(field, format!("`{}` has to be greater than 0", field))
+        }
+    }
+
+    /// Check that a referenced prototype exists. References of every prototype are checked when
+    /// it's validated, `Validate` implementations don't have to.
+    pub fn reference(&mut self, reference: &Reference) {
+        let exists = self
+            .known
+            .get(reference.category)
+            .is_some_and(|prototypes| prototypes.contains_key(reference.name));
+        if !exists {
+            self.field_error(
+                reference.field,
+                format!(
+                    "`{}` references {} prototype `{}`, which doesn't exist",
+                    reference.field, reference.category, reference.name
+                ),
+            )
+        }
+    }
+
+    /// Warn about a field that is set but doesn't do anything.
+    pub fn irrelevant(&mut self, field: &str, reason: &str) {
+        if self.fields.contains(field) {
+            self.field_warning(field, format!("`{}` has no effect {}", field, reason))
+        }
+    }
+}
+
+pub trait Validate {
+    fn validate(&self, validator: &mut Validator);
+}
+
+impl Validate for MovementPrototype {
+    fn validate(&self, validator: &mut Validator) {
+        validator.non_negative("speed", self.speed);
+        validator.non_negative("max_speed", self.max_speed);
+        if let Some(max_speed_backwards) = self.max_speed_backwards {
+            validator.non_negative("max_speed_backwards", max_speed_backwards);
+        }
+        validator.non_negative("acceleration", self.acceleration);
+        if let Some(braking_acceleration) = self.braking_acceleration {
+            validator.non_negative("braking_acceleration", braking_acceleration);
+        }
+        validator.non_negative("passive_deceleration", self.passive_deceleration);
+        validator.non_negative("rotation_speed", self.rotation_speed);
+        match self.movement_type {
+            MovementType::Omnidirectional => {
+                let reason = "on omnidirectional movement";
+                for field in [
+                    "max_speed",
+                    "max_speed_backwards",
+                    "acceleration",
+                    "braking_acceleration",
+                    "passive_deceleration",
+                    "rotation_offset",
+                ] {
+                    validator.irrelevant(field, reason);
+                }
+            }
+            MovementType::AcceleratedSteering => {
+                validator.positive("max_speed", self.max_speed);
+                validator.positive("acceleration", self.acceleration);
+                validator.positive("rotation_speed", self.rotation_speed);
+                validator.irrelevant("speed", "on accelerated steering movement");
+            }
+            MovementType::Train => {
+                validator.warning("train movement is not implemented yet".to_string())
+            }
+        }
+    }
+}
+
+impl Validate for RadioPrototype {
+    fn validate(&self, validator: &mut Validator) {
+        validator.positive("range", self.range);
+        validator.positive("bandwidth", self.bandwidth);
+        validator.not_zero("max_message_size", self.max_message_size);
+        validator.not_zero("mailbox_size", self.mailbox_size);
+        if self.max_message_size as f32 > self.bandwidth {
+            validator.warning(
+                "`max_message_size` is larger than `bandwidth`, such messages can't be sent"
+                    .to_string(),
+            )
+        }
+    }
+}
+
+impl Validate for BlackBoxPrototype {
+    fn validate(&self, validator: &mut Validator) {
+        validator.not_zero("capacity", self.capacity);
+    }
+}
+
+impl Validate for BlackBoxReader {
+    fn validate(&self, validator: &mut Validator) {
+        validator.positive("range", self.range);
+    }
+}
+
+impl Validate for Item {
+    fn validate(&self, validator: &mut Validator) {
+        validator.not_zero("stack_size", self.stack_size as usize);
+        if let Some(data_capacity) = self.data_capacity {
+            validator.not_zero("data_capacity", data_capacity);
+            if self.stack_size != 1 {
+                validator.irrelevant("stack_size", "on items carrying data, they don't stack");
+            }
+        }
+    }
+}
+
+impl Validate for InventoryPrototype {
+    fn validate(&self, validator: &mut Validator) {
+        validator.not_zero("slots", self.slots);
+        if let Some(max_stack_size) = self.max_stack_size {
+            validator.not_zero("max_stack_size", max_stack_size as usize);
+        }
+        validator.non_negative("transfer_range", self.transfer_range);
+    }
+}
+
+impl Validate for ManipulatorPrototype {
+    fn validate(&self, validator: &mut Validator) {
+        validator.non_negative("reach", self.reach);
+        validator.positive("speed", self.speed);
+        match self.area {
+            PickupArea::Circle { radius } => validator.positive("area.radius", radius),
+            PickupArea::Rectangle { width, height } => {
+                validator.positive("area.width", width);
+                validator.positive("area.height", height);
+            }
+        }
+    }
+}
+
+impl Validate for Processor {
+    fn validate(&self, validator: &mut Validator) {
+        validator.not_zero("memory_limit", self.memory_limit);
+    }
+}
+
+impl Validate for UnitPrototype {
+    fn validate(&self, validator: &mut Validator) {
+        validator.positive("health", self.health);
+        match self.collider {
+            ColliderShape::Circle { radius } => validator.positive("collider.radius", radius),
+            ColliderShape::Rectangle { width, height } => {
+                validator.positive("collider.width", width);
+                validator.positive("collider.height", height);
+            }
+        }
+    }
+}
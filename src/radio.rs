@@ -0,0 +1,75 @@
+//! Radio messaging between units. Messages sent during a tick are delivered to every unit with a
+//! radio in range of the sender on the next tick.
+
+use crate::{data_value::DataValue, prototypes::Radio};
+use bevy::prelude::*;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Clone)]
+pub struct RadioMessage {
+    pub sender: Entity,
+    pub channel: u32,
+    pub data: DataValue,
+}
+
+#[derive(Debug, Error)]
+pub enum RadioError {
+    #[error("message is {size} bytes, which is more than the maximum of {max} bytes")]
+    MessageTooLarge { size: usize, max: usize },
+    #[error("not enough bandwidth: message is {size} bytes, only {available} bytes available")]
+    NotEnoughBandwidth { size: usize, available: usize },
+}
+
+impl Radio {
+    /// Queue a message to be broadcast to units in range. Fails if the message is larger than the
+    /// radio allows or if there isn't enough bandwidth left to send it right now.
+    pub fn send(
+        &mut self,
+        sender: Entity,
+        channel: u32,
+        data: DataValue,
+    ) -> Result<(), RadioError> {
+        let size = data.size();
+        if size > self.max_message_size {
+            return Err(RadioError::MessageTooLarge {
+                size,
+                max: self.max_message_size,
+            });
+        }
+        let available = self.bandwidth_available();
+        if size as f32 > available {
+            return Err(RadioError::NotEnoughBandwidth {
+                size,
+                available: available as usize,
+            });
+        }
+        self.bandwidth_used += size as f32;
+        self.outbox.push(RadioMessage {
+            sender,
+            channel,
+            data,
+        });
+        Ok(())
+    }
+
+    /// Put a message into the mailbox. Messages that don't fit are dropped.
+    pub fn receive(&mut self, message: RadioMessage) -> bool {
+        if self.mailbox.len() < self.mailbox_size {
+            self.mailbox.push_back(message);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Bytes that can be sent right now. A radio starts with a full second worth of bandwidth.
+    pub fn bandwidth_available(&self) -> f32 {
+        (self.bandwidth - self.bandwidth_used).max(0.0)
+    }
+
+    /// Restore bandwidth over time, at most one second worth of it can be accumulated.
+    pub fn refill_bandwidth(&mut self, delta: Duration) {
+        self.bandwidth_used = (self.bandwidth_used - self.bandwidth * delta.as_secs_f32()).max(0.0);
+    }
+}
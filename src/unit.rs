@@ -0,0 +1,167 @@
+//! Spawning units assembled from a unit prototype.
+
+use crate::{
+    black_box::DroppedBlackBox,
+    items::spawn_world_item,
+    program::UnitProgram,
+    prototypes::{
+        BlackBox, ColliderShape, ComponentPrototype, Inventory, Prototype, PrototypeRef,
+        Prototypes, UnitPrototype,
+    },
+    Destroyed, Unit, UnitClock,
+};
+use bevy::{
+    ecs::{query::WorldQuery, system::EntityCommands},
+    prelude::*,
+    time::Stopwatch,
+};
+use bevy_rapier2d::prelude::*;
+use thiserror::Error;
+
+/// Remaining health of a unit, the unit is destroyed when it drops to 0.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Health(pub f32);
+
+#[derive(Debug, Error)]
+pub enum SpawnUnitError {
+    #[error("unit prototype `{0}` doesn't exist")]
+    UnknownUnit(String),
+    #[error("{category} prototype `{name}` used by the unit doesn't exist")]
+    MissingPart {
+        category: &'static str,
+        name: String,
+    },
+}
+
+impl ColliderShape {
+    pub fn to_collider(&self) -> Collider {
+        match *self {
+            Self::Circle { radius } => Collider::ball(radius),
+            Self::Rectangle { width, height } => Collider::cuboid(width / 2.0, height / 2.0),
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        match *self {
+            Self::Circle { radius } => Vec2::splat(radius * 2.0),
+            Self::Rectangle { width, height } => Vec2::new(width, height),
+        }
+    }
+}
+
+/// Spawn a unit with all parts of its prototype, running the program.
+pub fn spawn_unit(
+    commands: &mut Commands,
+    prototypes: &Prototypes,
+    asset_server: &AssetServer,
+    name: &str,
+    transform: Transform,
+    program: &[u8],
+) -> Result<Entity, SpawnUnitError> {
+    let unit = UnitPrototype::from_pt(prototypes, name)
+        .ok_or_else(|| SpawnUnitError::UnknownUnit(name.to_string()))?;
+    let processor = part(prototypes, &unit.processor)?;
+    let movement = part(prototypes, &unit.movement)?;
+    let radio = part(prototypes, &unit.radio)?;
+    let black_box = part(prototypes, &unit.black_box)?;
+    let black_box_reader = part(prototypes, &unit.black_box_reader)?;
+    let inventory = part(prototypes, &unit.inventory)?;
+    let manipulator = part(prototypes, &unit.manipulator)?;
+
+    let unit_program = match processor {
+        Some(processor) => UnitProgram::new_lua_for_processor(processor, program),
+        None => UnitProgram::new_lua_with_program(program),
+    };
+    let mut entity = commands.spawn();
+    entity
+        .insert(Unit)
+        .insert(UnitClock(Stopwatch::default()))
+        .insert(Health(unit.health))
+        .insert(unit_program)
+        .insert(unit.collider.to_collider())
+        .insert(RigidBody::KinematicPositionBased)
+        .insert_bundle(SpriteBundle {
+            texture: asset_server.load(unit.sprite.as_str()),
+            transform,
+            sprite: Sprite {
+                custom_size: Some(unit.collider.size()),
+                ..default()
+            },
+            ..default()
+        });
+    insert_part(&mut entity, movement);
+    insert_part(&mut entity, radio);
+    insert_part(&mut entity, black_box);
+    insert_part(&mut entity, black_box_reader);
+    insert_part(&mut entity, inventory);
+    insert_part(&mut entity, manipulator);
+    Ok(entity.id())
+}
+
+fn part<'a, P: Prototype<'static> + 'static>(
+    prototypes: &'a Prototypes,
+    reference: &Option<PrototypeRef<P>>,
+) -> Result<Option<&'a P>, SpawnUnitError> {
+    match reference {
+        Some(reference) => {
+            reference
+                .get(prototypes)
+                .map(Some)
+                .ok_or_else(|| SpawnUnitError::MissingPart {
+                    category: P::category(),
+                    name: reference.name().to_string(),
+                })
+        }
+        None => Ok(None),
+    }
+}
+
+fn insert_part<'a, C: Component, P: ComponentPrototype<'a, C>>(
+    entity: &mut EntityCommands,
+    part: Option<&P>,
+) {
+    if let Some(part) = part {
+        entity.insert(part.to_component());
+    }
+}
+
+/// Parts of a destroyed unit that are left where it was.
+#[derive(WorldQuery)]
+pub struct DestroyedUnit<'w> {
+    entity: Entity,
+    transform: &'w Transform,
+    black_box: Option<&'w BlackBox>,
+    inventory: Option<&'w Inventory>,
+}
+
+/// Mark units without health left as destroyed.
+pub fn destroy_dead_units(
+    mut commands: Commands,
+    units: Query<(Entity, &Health), Without<Destroyed>>,
+) {
+    for (entity, health) in units.iter() {
+        if health.0 <= 0.0 {
+            commands.entity(entity).insert(Destroyed);
+        }
+    }
+}
+
+/// Remove destroyed units, dropping their black box and items where they were.
+pub fn destroy_units(
+    mut commands: Commands,
+    units: Query<DestroyedUnit, (With<Unit>, With<Destroyed>)>,
+) {
+    for unit in units.iter() {
+        let position = unit.transform.translation.truncate();
+        if let Some(black_box) = unit.black_box {
+            spawn_world_item(&mut commands, black_box.to_item_stack(), position)
+                .insert(DroppedBlackBox);
+        }
+        if let Some(inventory) = unit.inventory {
+            for stack in &inventory.stacks {
+                spawn_world_item(&mut commands, stack.clone(), position);
+            }
+        }
+        commands.entity(unit.entity).despawn();
+    }
+}
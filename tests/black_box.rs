@@ -0,0 +1,185 @@
+//! Storing data in black boxes and dropping them when their unit is destroyed.
+
+use bevy::{prelude::*, time::Stopwatch};
+use scriplets::{
+    black_box::{self, BlackBoxError, DroppedBlackBox},
+    data_value::DataValue,
+    items::{ItemData, ItemStack, WorldItem},
+    program::{UnitHandle, UnitProgram},
+    prototypes::{BlackBox, BlackBoxPrototype, ComponentPrototype, PrototypeRef, Prototypes},
+    unit::{destroy_dead_units, destroy_units, Health},
+    GameClock, Unit, UnitClock,
+};
+
+fn black_box() -> BlackBox {
+    BlackBoxPrototype {
+        name: "test".to_string(),
+        capacity: 20,
+        item: PrototypeRef::new("black-box".to_string()),
+    }
+    .to_component()
+}
+
+#[test]
+fn written_values_can_be_read() {
+    let mut black_box = black_box();
+    black_box
+        .write("a".to_string(), DataValue::Integer(1))
+        .unwrap();
+    assert_eq!(black_box.read("a"), DataValue::Integer(1));
+    assert_eq!(black_box.read("b"), DataValue::Nil);
+    assert_eq!(black_box.used(), 1 + DataValue::Integer(1).size());
+}
+
+#[test]
+fn writing_nil_removes_the_key() {
+    let mut black_box = black_box();
+    black_box
+        .write("a".to_string(), DataValue::Integer(1))
+        .unwrap();
+    black_box.write("a".to_string(), DataValue::Nil).unwrap();
+    assert_eq!(black_box.read("a"), DataValue::Nil);
+    assert_eq!(black_box.used(), 0);
+}
+
+#[test]
+fn writes_over_the_capacity_fail() {
+    let mut black_box = black_box();
+    black_box
+        .write("a".to_string(), DataValue::String("x".repeat(10)))
+        .unwrap();
+    assert!(matches!(
+        black_box.write("b".to_string(), DataValue::String("x".repeat(10))),
+        Err(BlackBoxError::Full(_))
+    ));
+    assert_eq!(black_box.read("b"), DataValue::Nil);
+}
+
+#[test]
+fn dropped_black_box_carries_the_data() {
+    let mut black_box = black_box();
+    black_box
+        .write("a".to_string(), DataValue::Integer(1))
+        .unwrap();
+    let stack = black_box.to_item_stack();
+    assert_eq!(stack.item, "black-box");
+    assert_eq!(stack.count, 1);
+    let payload = stack.data.unwrap().payload;
+    assert_eq!(
+        black_box::read_dropped(&payload, "a"),
+        DataValue::Integer(1)
+    );
+    assert_eq!(black_box::read_dropped(&payload, "b"), DataValue::Nil);
+}
+
+#[test]
+fn destroyed_unit_drops_its_black_box() {
+    let mut world = World::new();
+    let mut black_box = black_box();
+    black_box
+        .write("a".to_string(), DataValue::Integer(1))
+        .unwrap();
+    let unit = world
+        .spawn()
+        .insert(Unit)
+        .insert(Health(0.0))
+        .insert(Transform::from_xyz(3.0, 4.0, 0.0))
+        .insert(black_box.clone())
+        .id();
+
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(destroy_dead_units);
+    stage.add_system(destroy_units);
+    // Units are marked as destroyed on the first frame and removed on the next one
+    stage.run(&mut world);
+    assert!(world.get_entity(unit).is_some());
+    stage.run(&mut world);
+    assert!(world.get_entity(unit).is_none());
+
+    let mut dropped = world.query_filtered::<(&WorldItem, &Transform), With<DroppedBlackBox>>();
+    let (item, transform) = dropped.single(&world);
+    assert_eq!(
+        item.0,
+        ItemStack {
+            item: "black-box".to_string(),
+            count: 1,
+            data: Some(ItemData {
+                payload: black_box.to_data_value(),
+                key: None,
+            }),
+        }
+    );
+    assert_eq!(transform.translation.truncate(), Vec2::new(3.0, 4.0));
+}
+
+#[test]
+fn living_units_are_not_destroyed() {
+    let mut world = World::new();
+    let unit = world
+        .spawn()
+        .insert(Unit)
+        .insert(Health(1.0))
+        .insert(Transform::default())
+        .insert(black_box())
+        .id();
+
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(destroy_dead_units);
+    stage.add_system(destroy_units);
+    stage.run(&mut world);
+    stage.run(&mut world);
+    assert!(world.get_entity(unit).is_some());
+    assert_eq!(world.query::<&WorldItem>().iter(&world).count(), 0);
+}
+
+#[test]
+fn self_destructed_unit_drops_its_black_box() {
+    let mut program = UnitProgram::new_lua_with_program(
+        br#"
+function on_tick(handle)
+    handle:black_box_write("last words", "goodbye")
+    handle:self_destruct()
+end
+"#,
+    );
+    let mut black_box = black_box();
+    let mut health = Health(10.0);
+    let (prototypes, _) = Prototypes::from_packs(&[]).unwrap();
+    program.tick(UnitHandle {
+        entity: Entity::from_raw(0),
+        movement: None,
+        radio: None,
+        black_box: Some(&mut black_box),
+        nearby_black_boxes: Vec::new(),
+        inventory: None,
+        manipulator: None,
+        nearby_items: Vec::new(),
+        health: Some(&mut health),
+        transform: &Transform::default(),
+        clock: &UnitClock(Stopwatch::default()),
+        game_clock: &GameClock(Stopwatch::default()),
+        prototypes: &prototypes,
+    });
+    assert_eq!(health.0, 0.0);
+
+    let mut world = World::new();
+    world
+        .spawn()
+        .insert(Unit)
+        .insert(health)
+        .insert(Transform::default())
+        .insert(black_box);
+    let mut stage = SystemStage::single_threaded();
+    stage.add_system(destroy_dead_units);
+    stage.add_system(destroy_units);
+    stage.run(&mut world);
+    stage.run(&mut world);
+    assert_eq!(world.query::<&Unit>().iter(&world).count(), 0);
+
+    let mut dropped = world.query_filtered::<&WorldItem, With<DroppedBlackBox>>();
+    let payload = &dropped.single(&world).0.data.as_ref().unwrap().payload;
+    assert_eq!(
+        black_box::read_dropped(payload, "last words"),
+        DataValue::String("goodbye".to_string())
+    );
+}
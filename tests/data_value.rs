@@ -0,0 +1,384 @@
+//! Round trips of data values through the binary encoding and through Lua, paths and patches.
+
+use mlua::prelude::*;
+use proptest::prelude::*;
+use scriplets::data_value::{
+    binary::DecodeError,
+    path::PathError,
+    storage::{DataStorage, QuotaError},
+    DataValue, Number, MAX_DEPTH,
+};
+use std::collections::BTreeMap;
+
+/// Values in the shape converting from Lua gives them: no nil in tables, and tables with keys
+/// from 1 to their length are sequences.
+fn lua_shaped(value: DataValue) -> DataValue {
+    match value {
+        DataValue::Sequence(seq) => DataValue::Sequence(seq.into_iter().map(lua_shaped).collect()),
+        DataValue::Table(table) => {
+            let table: BTreeMap<_, _> = table
+                .into_iter()
+                .filter(|(_, value)| *value != DataValue::Nil)
+                .map(|(key, value)| (key, lua_shaped(value)))
+                .collect();
+            let is_sequence = table
+                .keys()
+                .enumerate()
+                .all(|(i, key)| *key == DataValue::Integer(i as i64 + 1));
+            if is_sequence {
+                DataValue::Sequence(table.into_values().collect())
+            } else {
+                DataValue::Table(table)
+            }
+        }
+        value => value,
+    }
+}
+
+fn data_value() -> impl Strategy<Value = DataValue> {
+    let leaf = prop_oneof![
+        Just(DataValue::Nil),
+        any::<bool>().prop_map(DataValue::Boolean),
+        any::<i64>().prop_map(DataValue::Integer),
+        any::<f64>().prop_map(|n| DataValue::Number(Number::new(n))),
+        ".*".prop_map(DataValue::String),
+        prop::collection::vec(any::<u8>(), 0..16).prop_map(DataValue::Bytes),
+    ];
+    leaf.prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(DataValue::Sequence),
+            prop::collection::btree_map(inner.clone(), inner, 0..8).prop_map(DataValue::Table),
+        ]
+    })
+}
+
+proptest! {
+    #[test]
+    fn round_trip(value in data_value()) {
+        let bytes = value.to_bytes();
+        prop_assert_eq!(DataValue::from_bytes(&bytes), Ok(value));
+    }
+
+    #[test]
+    fn encoded_size_is_exact(value in data_value()) {
+        prop_assert_eq!(value.encoded_size(), value.to_bytes().len());
+    }
+
+    #[test]
+    fn truncated_data_is_rejected(value in data_value(), cut in any::<prop::sample::Index>()) {
+        let bytes = value.to_bytes();
+        let cut = cut.index(bytes.len());
+        prop_assert!(DataValue::from_bytes(&bytes[..cut]).is_err());
+    }
+
+    #[test]
+    fn storage_tracks_used_size(entries in prop::collection::vec((".{0,4}", data_value()), 0..16)) {
+        let mut storage = DataStorage::new(usize::MAX);
+        for (key, value) in entries {
+            storage.write(key, value).unwrap();
+        }
+        let used: usize = storage.iter().map(|(key, value)| key.len() + value.size()).sum();
+        prop_assert_eq!(storage.used(), used);
+    }
+
+    #[test]
+    fn merging_diff_gives_other_value(old in data_value(), new in data_value()) {
+        let (mut old, new) = (lua_shaped(old), lua_shaped(new));
+        if let Some(patch) = old.diff(&new) {
+            old.merge(patch);
+        }
+        prop_assert_eq!(old, new);
+    }
+
+    #[test]
+    fn arbitrary_bytes_dont_panic(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+        let _ = DataValue::from_bytes(&bytes);
+    }
+}
+
+#[test]
+fn integers_and_numbers_stay_distinct() {
+    for value in [DataValue::Integer(1), DataValue::Number(Number::new(1.0))] {
+        assert_eq!(DataValue::from_bytes(&value.to_bytes()), Ok(value));
+    }
+}
+
+#[test]
+fn sequences_and_tables_with_integer_keys_stay_distinct() {
+    let sequence = DataValue::Sequence(vec![DataValue::Boolean(true)]);
+    let table = DataValue::Table(BTreeMap::from([(
+        DataValue::Integer(1),
+        DataValue::Boolean(true),
+    )]));
+    assert_ne!(sequence.to_bytes(), table.to_bytes());
+    assert_eq!(DataValue::from_bytes(&sequence.to_bytes()), Ok(sequence));
+    assert_eq!(DataValue::from_bytes(&table.to_bytes()), Ok(table));
+}
+
+#[test]
+fn trailing_bytes_are_rejected() {
+    let mut bytes = DataValue::Nil.to_bytes();
+    bytes.push(0);
+    assert_eq!(
+        DataValue::from_bytes(&bytes),
+        Err(DecodeError::TrailingBytes(1))
+    );
+}
+
+#[test]
+fn repeated_keys_are_rejected() {
+    // Table of 2 entries, both with the key nil
+    let bytes = [7, 2, 0, 0, 0, 1];
+    assert_eq!(DataValue::from_bytes(&bytes), Err(DecodeError::RepeatedKey));
+}
+
+#[test]
+fn deeply_nested_data_is_rejected() {
+    // Sequences of 1 element, each holding the next one
+    let mut bytes = [6, 1].repeat(MAX_DEPTH + 1);
+    bytes.push(0);
+    assert_eq!(DataValue::from_bytes(&bytes), Err(DecodeError::TooDeep));
+}
+
+fn from_lua(lua: &Lua, chunk: &str) -> LuaResult<DataValue> {
+    lua.load(chunk).eval()
+}
+
+#[test]
+fn lua_sequences_and_mixed_tables() {
+    let lua = Lua::new();
+    assert_eq!(
+        from_lua(&lua, "{1, 2}").unwrap(),
+        DataValue::Sequence(vec![DataValue::Integer(1), DataValue::Integer(2)])
+    );
+    assert_eq!(
+        from_lua(&lua, "{}").unwrap(),
+        DataValue::Sequence(Vec::new())
+    );
+    let mixed = from_lua(&lua, "{1, 2, x = 3}").unwrap();
+    assert_eq!(
+        mixed,
+        DataValue::Table(BTreeMap::from([
+            (DataValue::Integer(1), DataValue::Integer(1)),
+            (DataValue::Integer(2), DataValue::Integer(2)),
+            (DataValue::String("x".into()), DataValue::Integer(3)),
+        ]))
+    );
+    let holes = from_lua(&lua, "{[1] = true, [3] = true}").unwrap();
+    assert!(matches!(holes, DataValue::Table(_)));
+}
+
+#[test]
+fn lua_numbers_keep_precision() {
+    let lua = Lua::new();
+    let value = from_lua(&lua, "0.1").unwrap();
+    assert_eq!(value, DataValue::Number(Number::new(0.1)));
+    let nan = from_lua(&lua, "0/0").unwrap();
+    assert_eq!(nan, DataValue::Number(Number::new(f64::NAN)));
+    assert_eq!(
+        nan.clone().to_lua(&lua).map(|v| v.type_name()).unwrap(),
+        "number"
+    );
+}
+
+#[test]
+fn lua_tables_containing_themselves_are_rejected() {
+    let lua = Lua::new();
+    assert!(from_lua(&lua, "local t = {} t.t = t return t").is_err());
+    assert!(from_lua(&lua, "local t = {} return {t, t}").is_ok());
+}
+
+#[test]
+fn lua_tables_nested_too_deep_are_rejected() {
+    let lua = Lua::new();
+    let nested = |depth| format!("{}{}", "{".repeat(depth), "}".repeat(depth));
+    assert!(from_lua(&lua, &format!("return {}", nested(MAX_DEPTH))).is_ok());
+    assert!(from_lua(&lua, &format!("return {}", nested(MAX_DEPTH + 1))).is_err());
+}
+
+#[test]
+fn storage_rejects_writes_over_capacity() {
+    let mut storage = DataStorage::new(10);
+    // 1 byte of key, 2 bytes of value
+    storage.write("a".into(), DataValue::Integer(1)).unwrap();
+    assert_eq!(storage.used(), 3);
+    assert_eq!(
+        storage.write("b".into(), DataValue::String("too long".into())),
+        Err(QuotaError {
+            size: 11,
+            available: 7,
+            capacity: 10
+        })
+    );
+    assert_eq!(storage.get("b"), None);
+    assert_eq!(storage.used(), 3);
+    // Replacing a value only needs room for the difference
+    storage
+        .write("a".into(), DataValue::String("1234567".into()))
+        .unwrap();
+    assert_eq!(storage.used(), 10);
+    storage.write("a".into(), DataValue::Nil).unwrap();
+    assert_eq!(storage.used(), 0);
+    assert!(storage.is_empty());
+}
+
+#[test]
+fn storage_over_lowered_capacity_can_shrink() {
+    let mut storage = DataStorage::new(10);
+    storage
+        .write("a".into(), DataValue::String("12345".into()))
+        .unwrap();
+    storage.set_capacity(4);
+    assert!(storage.write("b".into(), DataValue::Nil).is_ok());
+    assert!(storage.write("b".into(), DataValue::Boolean(true)).is_err());
+    storage.write("a".into(), DataValue::Boolean(true)).unwrap();
+    assert_eq!(storage.used(), 2);
+}
+
+#[test]
+fn binary_lua_strings_round_trip() {
+    let lua = Lua::new();
+    let value = from_lua(&lua, r#"return "\xff\0packed""#).unwrap();
+    assert_eq!(value, DataValue::Bytes(b"\xff\0packed".to_vec()));
+    let value = DataValue::from_bytes(&value.to_bytes()).unwrap();
+    let string: LuaString = lua.unpack(value.to_lua(&lua).unwrap()).unwrap();
+    assert_eq!(string.as_bytes(), b"\xff\0packed");
+}
+
+#[test]
+fn strings_and_bytes_stay_distinct() {
+    let string = DataValue::String("text".into());
+    let bytes = DataValue::Bytes(b"text".to_vec());
+    assert_ne!(string.to_bytes(), bytes.to_bytes());
+    assert_eq!(DataValue::from_bytes(&string.to_bytes()), Ok(string));
+    assert_eq!(DataValue::from_bytes(&bytes.to_bytes()), Ok(bytes));
+}
+
+#[test]
+fn json_bytes_come_back_as_sequences_of_integers() {
+    let bytes = DataValue::Bytes(vec![1, 255]);
+    let json = serde_json::to_string(&bytes).unwrap();
+    assert_eq!(json, "[1,255]");
+    assert_eq!(
+        serde_json::from_str::<DataValue>(&json).unwrap(),
+        DataValue::Sequence(vec![DataValue::Integer(1), DataValue::Integer(255)])
+    );
+}
+
+fn unit_data() -> DataValue {
+    let lua = Lua::new();
+    from_lua(
+        &lua,
+        r#"{inventory = {slots = {{item = "ore", count = 5}, {item = "plate", count = 2}}}}"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn paths_get_nested_values() {
+    let data = unit_data();
+    assert_eq!(data.get_integer("inventory.slots[1].count"), Ok(2));
+    assert_eq!(data.get_str("inventory.slots[0].item"), Ok("ore"));
+    assert_eq!(data.get_sequence("inventory.slots").map(<[_]>::len), Ok(2));
+    assert_eq!(data.get("").unwrap(), &data);
+    assert_eq!(
+        data.get("inventory.slots[2].count"),
+        Err(PathError::NotFound("inventory.slots[2]".into()))
+    );
+    assert_eq!(
+        data.get_number("inventory.slots[0].item"),
+        Err(PathError::WrongType {
+            path: "inventory.slots[0].item".into(),
+            expected: "number",
+            found: "string"
+        })
+    );
+}
+
+#[test]
+fn invalid_paths_are_rejected() {
+    let data = unit_data();
+    for path in [
+        "inventory.",
+        ".inventory",
+        "inventory..slots",
+        "slots[",
+        "slots[-1]",
+        "slots[9223372036854775807]",
+        "slots[18446744073709551615]",
+        "a]b",
+    ] {
+        assert!(
+            matches!(data.get(path), Err(PathError::Invalid { .. })),
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn paths_set_and_delete_values() {
+    let mut data = unit_data();
+    assert_eq!(
+        data.set("inventory.slots[1].count", DataValue::Integer(3)),
+        Ok(Some(DataValue::Integer(2)))
+    );
+    assert_eq!(data.get_integer("inventory.slots[1].count"), Ok(3));
+    // Missing tables are created, appending keeps a sequence
+    let gear = DataValue::String("gear".into());
+    data.set("inventory.slots[2].item", gear).unwrap();
+    assert_eq!(data.get_sequence("inventory.slots").map(<[_]>::len), Ok(3));
+    let target = DataValue::Integer(7);
+    data.set("memory.targets[0]", target).unwrap();
+    assert_eq!(
+        data.get("memory.targets"),
+        Ok(&DataValue::Sequence(vec![DataValue::Integer(7)]))
+    );
+    assert!(matches!(
+        data.set("inventory.slots[0].count.x", DataValue::Boolean(true)),
+        Err(PathError::WrongType { .. })
+    ));
+    // Removing from the middle leaves a hole, like in Lua
+    assert!(data.delete("inventory.slots[0]").unwrap().is_some());
+    assert!(data.get_table("inventory.slots").is_ok());
+    assert_eq!(data.get_integer("inventory.slots[1].count"), Ok(3));
+    assert_eq!(data.delete("inventory.slots[0]"), Ok(None));
+}
+
+/// Table with string keys.
+fn table<const N: usize>(entries: [(&str, DataValue); N]) -> DataValue {
+    DataValue::Table(
+        entries
+            .into_iter()
+            .map(|(key, value)| (DataValue::String(key.into()), value))
+            .collect(),
+    )
+}
+
+#[test]
+fn diff_keeps_unchanged_keys_out() {
+    let old = unit_data();
+    let mut new = old.clone();
+    let count = DataValue::Integer(4);
+    new.set("inventory.slots[0].count", count).unwrap();
+    new.set("label", DataValue::String("miner".into())).unwrap();
+    let patch = old.diff(&new).unwrap();
+    let slots = DataValue::Sequence(vec![
+        table([
+            ("count", DataValue::Integer(4)),
+            ("item", DataValue::String("ore".into())),
+        ]),
+        table([
+            ("count", DataValue::Integer(2)),
+            ("item", DataValue::String("plate".into())),
+        ]),
+    ]);
+    assert_eq!(
+        patch,
+        table([
+            ("inventory", table([("slots", slots)])),
+            ("label", DataValue::String("miner".into())),
+        ])
+    );
+    assert_eq!(old.diff(&old), None);
+}
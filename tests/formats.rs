@@ -0,0 +1,129 @@
+//! Locations of prototypes in RON and TOML files, which are found by scanning the source, and
+//! locations of errors in JSON files, which come from the parser.
+
+use scriplets::prototypes::{PrototypesError, PrototypesFragment, SourceLocation};
+use std::path::Path;
+
+fn location(line: usize, column: usize, file: &str) -> SourceLocation {
+    SourceLocation {
+        file: file.into(),
+        line,
+        column,
+    }
+}
+
+fn entry_locations(source: &str, file: &str) -> Vec<SourceLocation> {
+    let fragment = PrototypesFragment::from_bytes(source.as_bytes(), Path::new(file)).unwrap();
+    fragment.categories["item"]
+        .iter()
+        .map(|entry| entry.location.clone())
+        .collect()
+}
+
+fn category_error_location(source: &str, file: &str) -> SourceLocation {
+    match PrototypesFragment::from_bytes(source.as_bytes(), Path::new(file)) {
+        Err(PrototypesError::InvalidCategory { location, .. }) => location,
+        other => panic!("expected an invalid category, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn ron_prototypes_are_located_by_their_names() {
+    let source = r#"// The "item" category, "name": "plate"
+{
+    "item": [
+        {
+            "description": "\"name\": \"plate\"",
+            "name": "plate",
+            "stack_size": 10,
+        },
+        {"name": "plate-stack", "stack_size": 1},
+    ],
+}
+"#;
+    assert_eq!(
+        entry_locations(source, "items.ron"),
+        [location(6, 21, "items.ron"), location(9, 18, "items.ron")]
+    );
+}
+
+#[test]
+fn ron_categories_are_located_by_their_keys() {
+    let source = r#"/* "item": 5 */
+{
+    "items_list": [],
+    "item": 5,
+}
+"#;
+    assert_eq!(
+        category_error_location(source, "items.ron"),
+        location(4, 5, "items.ron")
+    );
+}
+
+#[test]
+fn toml_prototypes_are_located_by_their_names() {
+    let source = r#"# [[item]] name = 'plate'
+[[item]]
+description = "name = 'plate'"
+name = 'plate'
+stack_size = 10
+
+[[item]]
+name = "plate-stack"
+stack_size = 1
+"#;
+    assert_eq!(
+        entry_locations(source, "items.toml"),
+        [location(4, 8, "items.toml"), location(8, 8, "items.toml")]
+    );
+}
+
+#[test]
+fn toml_categories_are_located_by_their_keys() {
+    let source = r#"# item = 5
+other_item = []
+item = 5
+"#;
+    assert_eq!(
+        category_error_location(source, "items.toml"),
+        location(3, 1, "items.toml")
+    );
+}
+
+#[test]
+fn json_syntax_errors_are_located() {
+    let source = r#"{
+    "item": [
+        {"name": "plate", "stack_size": 10}
+        {"name": "gear", "stack_size": 10}
+    ]
+}
+"#;
+    match PrototypesFragment::from_bytes(source.as_bytes(), Path::new("items.json")) {
+        Err(PrototypesError::Syntax {
+            location: at,
+            message,
+        }) => {
+            assert_eq!(at, location(4, 9, "items.json"));
+            assert_eq!(message, "expected `,` or `]`");
+        }
+        other => panic!("expected a syntax error, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn json_type_errors_are_located_at_the_value() {
+    let source = r#"{
+    "item": [
+        {"name": "plate", "stack_size": 10},
+        "gear"
+    ]
+}
+"#;
+    // Like serde_json on the whole file, the error is at the end of the value
+    assert_eq!(
+        category_error_location(source, "items.json"),
+        location(4, 14, "items.json")
+    );
+}
@@ -0,0 +1,159 @@
+//! Framing of handshake messages and telling clients how their prototypes differ.
+
+use scriplets::{
+    handshake::{
+        read_message, server_handshake, write_message, ClientMessage, HandshakeError, ManifestDiff,
+        Refusal, ServerMessage, ServerPrototypes, MAX_CLIENT_MESSAGE_SIZE, PROTOCOL_VERSION,
+    },
+    prototypes::Manifest,
+};
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, Read, Write},
+    sync::Arc,
+};
+
+fn manifest(prototypes: &[(&str, &str, &str)]) -> Manifest {
+    let mut manifest = Manifest::default();
+    for (category, name, hash) in prototypes {
+        manifest
+            .0
+            .entry(category.to_string())
+            .or_insert_with(BTreeMap::new)
+            .insert(name.to_string(), hash.to_string());
+    }
+    manifest
+}
+
+fn key(category: &str, name: &str) -> (String, String) {
+    (category.to_string(), name.to_string())
+}
+
+#[test]
+fn same_manifests_have_no_differences() {
+    let manifest = manifest(&[("item", "plate", "1"), ("movement", "wheels", "2")]);
+    assert_eq!(
+        ManifestDiff::new(&manifest, &manifest),
+        ManifestDiff::default()
+    );
+}
+
+#[test]
+fn diff_lists_prototypes_on_one_side_and_changed_ones() {
+    let server = manifest(&[
+        ("item", "plate", "1"),
+        ("item", "gear", "2"),
+        ("movement", "wheels", "3"),
+    ]);
+    let client = manifest(&[
+        ("item", "plate", "1"),
+        ("item", "gear", "changed"),
+        ("radio", "short", "4"),
+    ]);
+    assert_eq!(
+        ManifestDiff::new(&server, &client),
+        ManifestDiff {
+            only_on_server: vec![key("movement", "wheels")],
+            only_on_client: vec![key("radio", "short")],
+            different: vec![key("item", "gear")],
+        }
+    );
+}
+
+#[test]
+fn messages_round_trip() {
+    let mut buffer = Vec::new();
+    write_message(&mut buffer, &ServerMessage::Welcome).unwrap();
+    assert_eq!(&buffer[..4], &(buffer.len() as u32 - 4).to_be_bytes());
+    let message: ServerMessage = read_message(&mut Cursor::new(buffer), 1024).unwrap();
+    assert!(matches!(message, ServerMessage::Welcome));
+}
+
+#[test]
+fn messages_over_the_limit_are_rejected() {
+    let mut buffer = Vec::new();
+    write_message(&mut buffer, &ServerMessage::Welcome).unwrap();
+    let result: Result<ServerMessage, _> = read_message(&mut Cursor::new(buffer), 4);
+    assert!(matches!(result, Err(HandshakeError::MessageTooLarge(_))));
+}
+
+#[test]
+fn announced_size_alone_is_not_trusted() {
+    // Only the length of a message the size of the limit arrives
+    let buffer = (MAX_CLIENT_MESSAGE_SIZE as u32).to_be_bytes().to_vec();
+    let result: Result<ClientMessage, _> =
+        read_message(&mut Cursor::new(buffer), MAX_CLIENT_MESSAGE_SIZE);
+    assert!(matches!(
+        result,
+        Err(HandshakeError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+    ));
+}
+
+/// Connection with what the peer sent already in `input`.
+struct Connection {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn handshake(hash: &str, manifest: Manifest) -> (Result<(), HandshakeError>, ServerMessage) {
+    let server = ServerPrototypes {
+        hash: "server".to_string(),
+        manifest: self::manifest(&[("item", "plate", "1")]),
+        canonical_json: Arc::new("{}".to_string()),
+    };
+    let mut input = Vec::new();
+    let hello = ClientMessage::Hello {
+        protocol_version: PROTOCOL_VERSION,
+        prototypes_hash: Some(hash.to_string()),
+        manifest,
+        accepts_prototypes: false,
+    };
+    write_message(&mut input, &hello).unwrap();
+    let mut connection = Connection {
+        input: Cursor::new(input),
+        output: Vec::new(),
+    };
+    let result = server_handshake(&mut connection, &server);
+    let response = read_message(&mut Cursor::new(connection.output), 1024).unwrap();
+    (result, response)
+}
+
+#[test]
+fn client_with_the_same_hash_is_welcome() {
+    let (result, response) = handshake("server", manifest(&[("item", "plate", "1")]));
+    assert!(result.is_ok());
+    assert!(matches!(response, ServerMessage::Welcome));
+}
+
+#[test]
+fn client_with_a_different_hash_gets_the_diff() {
+    let (result, response) = handshake("client", manifest(&[("item", "plate", "2")]));
+    let expected = ManifestDiff {
+        different: vec![key("item", "plate")],
+        ..Default::default()
+    };
+    assert!(matches!(
+        result,
+        Err(HandshakeError::Refused(Refusal::PrototypesMismatch(ref diff))) if *diff == expected
+    ));
+    assert!(matches!(
+        response,
+        ServerMessage::Refused(Refusal::PrototypesMismatch(ref diff)) if *diff == expected
+    ));
+}
@@ -0,0 +1,110 @@
+//! Applying reloaded prototypes to components of live entities.
+
+use bevy::{asset::AssetPlugin, prelude::*};
+use scriplets::{
+    items::ItemStack,
+    prototypes::{
+        reapply_prototypes, ComponentPrototype, Inventory, InventoryPrototype, Item,
+        PrototypeRemoved, Prototypes, PrototypesFragment,
+    },
+    PrototypesHandle,
+};
+use std::path::Path;
+
+fn prototypes(source: &str) -> Prototypes {
+    let fragment =
+        PrototypesFragment::from_json(source.as_bytes(), Path::new("test.json")).unwrap();
+    Prototypes::from_packs(&[vec![&fragment]]).unwrap().0
+}
+
+fn app(prototypes: Prototypes) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin)
+        .add_asset::<Prototypes>()
+        .add_system(reapply_prototypes::<InventoryPrototype, Inventory>);
+    let handle = app
+        .world
+        .resource_mut::<Assets<Prototypes>>()
+        .add(prototypes);
+    app.insert_resource(PrototypesHandle(handle));
+    app
+}
+
+fn spawn_inventory(app: &mut App, name: &str) -> Entity {
+    let handle = app.world.resource::<PrototypesHandle>().0.clone();
+    let assets = app.world.resource::<Assets<Prototypes>>();
+    let inventory = InventoryPrototype::component_from_pt(assets.get(&handle).unwrap(), name);
+    app.world.spawn().insert(inventory.unwrap()).id()
+}
+
+fn reload(app: &mut App, prototypes: Prototypes) {
+    let handle = app.world.resource::<PrototypesHandle>().0.clone();
+    app.world
+        .resource_mut::<Assets<Prototypes>>()
+        .set_untracked(handle, prototypes);
+    // Asset events are sent at the end of the frame and read on the next one
+    app.update();
+    app.update();
+}
+
+#[test]
+fn reloaded_prototypes_keep_the_state_of_components() {
+    let mut app = app(prototypes(
+        r#"{"inventory": [{"name": "small", "slots": 2}, {"name": "spare", "slots": 1}]}"#,
+    ));
+    let inventory = spawn_inventory(&mut app, "small");
+    let spare = spawn_inventory(&mut app, "spare");
+    let plate = Item {
+        name: "plate".to_string(),
+        stack_size: 10,
+        data_capacity: None,
+    };
+    app.world
+        .get_mut::<Inventory>(inventory)
+        .unwrap()
+        .insert(&plate, ItemStack::new("plate", 15));
+    app.update();
+
+    reload(
+        &mut app,
+        prototypes(r#"{"inventory": [{"name": "small", "slots": 8}]}"#),
+    );
+    let reloaded = app.world.get::<Inventory>(inventory).unwrap();
+    assert_eq!(reloaded.slots, 8);
+    assert_eq!(reloaded.count("plate"), 15);
+    assert!(app
+        .world
+        .get::<PrototypeRemoved<Inventory>>(inventory)
+        .is_none());
+    // Components of removed prototypes are marked and keep the old values
+    assert!(app
+        .world
+        .get::<PrototypeRemoved<Inventory>>(spare)
+        .is_some());
+    assert_eq!(app.world.get::<Inventory>(spare).unwrap().slots, 1);
+}
+
+#[test]
+fn prototypes_added_back_are_applied_again() {
+    let mut app = app(prototypes(
+        r#"{"inventory": [{"name": "spare", "slots": 1}]}"#,
+    ));
+    let spare = spawn_inventory(&mut app, "spare");
+    app.update();
+    reload(&mut app, prototypes(r#"{"inventory": []}"#));
+    assert!(app
+        .world
+        .get::<PrototypeRemoved<Inventory>>(spare)
+        .is_some());
+
+    reload(
+        &mut app,
+        prototypes(r#"{"inventory": [{"name": "spare", "slots": 3}]}"#),
+    );
+    assert!(app
+        .world
+        .get::<PrototypeRemoved<Inventory>>(spare)
+        .is_none());
+    assert_eq!(app.world.get::<Inventory>(spare).unwrap().slots, 3);
+}
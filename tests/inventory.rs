@@ -0,0 +1,203 @@
+//! Inserting and removing items in inventories with limited slots and stack sizes, and accessing
+//! data of items that carry it.
+
+use scriplets::{
+    data_value::DataValue,
+    items::{ItemData, ItemDataError, ItemStack},
+    prototypes::{
+        ComponentPrototype, Inventory, InventoryPrototype, Item, Prototypes, PrototypesFragment,
+    },
+};
+use std::path::Path;
+
+fn inventory(slots: usize, max_stack_size: Option<u32>) -> Inventory {
+    InventoryPrototype {
+        name: "test".to_string(),
+        slots,
+        max_stack_size,
+        transfer_range: 1.0,
+    }
+    .to_component()
+}
+
+fn plate() -> Item {
+    Item {
+        name: "plate".to_string(),
+        stack_size: 10,
+        data_capacity: None,
+    }
+}
+
+fn card() -> Item {
+    Item {
+        name: "card".to_string(),
+        stack_size: 10,
+        data_capacity: Some(64),
+    }
+}
+
+fn counts(inventory: &Inventory) -> Vec<u32> {
+    inventory.stacks.iter().map(|stack| stack.count).collect()
+}
+
+#[test]
+fn insert_splits_items_into_stacks() {
+    let mut inventory = inventory(3, None);
+    assert_eq!(
+        inventory.insert(&plate(), ItemStack::new("plate", 25)),
+        None
+    );
+    assert_eq!(counts(&inventory), [10, 10, 5]);
+    assert_eq!(inventory.count("plate"), 25);
+}
+
+#[test]
+fn insert_fills_existing_stacks_first() {
+    let mut inventory = inventory(3, None);
+    inventory.insert(&plate(), ItemStack::new("plate", 5));
+    inventory.insert(&plate(), ItemStack::new("plate", 8));
+    assert_eq!(counts(&inventory), [10, 3]);
+}
+
+#[test]
+fn insert_returns_what_does_not_fit() {
+    let mut inventory = inventory(2, None);
+    assert_eq!(
+        inventory.insert(&plate(), ItemStack::new("plate", 25)),
+        Some(ItemStack::new("plate", 5))
+    );
+    assert_eq!(counts(&inventory), [10, 10]);
+    assert_eq!(inventory.space_for(&plate()), 0);
+}
+
+#[test]
+fn inventory_stack_size_limits_item_stack_size() {
+    let mut inventory = inventory(2, Some(4));
+    assert_eq!(inventory.stack_size(&plate()), 4);
+    assert_eq!(inventory.space_for(&plate()), 8);
+    assert_eq!(
+        inventory.insert(&plate(), ItemStack::new("plate", 10)),
+        Some(ItemStack::new("plate", 2))
+    );
+    assert_eq!(counts(&inventory), [4, 4]);
+}
+
+#[test]
+fn items_carrying_data_do_not_stack() {
+    let mut inventory = inventory(3, None);
+    assert_eq!(inventory.stack_size(&card()), 1);
+    inventory.insert(&card(), ItemStack::new("card", 2));
+    assert_eq!(counts(&inventory), [1, 1]);
+}
+
+#[test]
+fn remove_takes_from_the_last_stacks_first() {
+    let mut inventory = inventory(3, None);
+    inventory.insert(&plate(), ItemStack::new("plate", 25));
+    assert_eq!(
+        inventory.remove("plate", 8),
+        [ItemStack::new("plate", 5), ItemStack::new("plate", 3)]
+    );
+    assert_eq!(counts(&inventory), [10, 7]);
+}
+
+#[test]
+fn remove_takes_at_most_what_is_there() {
+    let mut inventory = inventory(3, None);
+    inventory.insert(&plate(), ItemStack::new("plate", 5));
+    assert_eq!(inventory.remove("plate", 8), [ItemStack::new("plate", 5)]);
+    assert!(inventory.stacks.is_empty());
+    assert!(inventory.remove("plate", 1).is_empty());
+}
+
+#[test]
+fn removed_items_keep_their_data() {
+    let mut inventory = inventory(3, None);
+    let data = ItemData {
+        payload: Default::default(),
+        key: Some("key".to_string()),
+    };
+    let stack = ItemStack {
+        data: Some(data),
+        ..ItemStack::new("card", 1)
+    };
+    inventory.insert(&card(), stack.clone());
+    assert_eq!(inventory.remove("card", 1), [stack]);
+}
+
+fn prototypes() -> Prototypes {
+    let fragment = PrototypesFragment::from_json(
+        br#"{"item": [
+            {"name": "plate", "stack_size": 10},
+            {"name": "card", "stack_size": 1, "data_capacity": 16}
+        ]}"#,
+        Path::new("items.json"),
+    )
+    .unwrap();
+    Prototypes::from_packs(&[vec![&fragment]]).unwrap().0
+}
+
+/// Inventory with a card locked with `key` in the first slot.
+fn locked_card(prototypes: &Prototypes) -> Inventory {
+    let mut inventory = inventory(2, None);
+    inventory.insert(&card(), ItemStack::new("card", 1));
+    inventory
+        .set_item_key(prototypes, 0, Some("key".to_string()), None)
+        .unwrap();
+    inventory
+}
+
+#[test]
+fn locked_data_needs_the_key() {
+    let prototypes = prototypes();
+    let mut inventory = locked_card(&prototypes);
+    assert!(matches!(
+        inventory.write_item_data(&prototypes, 0, DataValue::Integer(1), None),
+        Err(ItemDataError::WrongKey)
+    ));
+    assert!(matches!(
+        inventory.read_item_data(&prototypes, 0, Some("other")),
+        Err(ItemDataError::WrongKey)
+    ));
+    inventory
+        .write_item_data(&prototypes, 0, DataValue::Integer(1), Some("key"))
+        .unwrap();
+    assert_eq!(
+        inventory
+            .read_item_data(&prototypes, 0, Some("key"))
+            .unwrap(),
+        DataValue::Integer(1)
+    );
+}
+
+#[test]
+fn data_over_the_capacity_is_not_written() {
+    let prototypes = prototypes();
+    let mut inventory = locked_card(&prototypes);
+    let payload = DataValue::String("x".repeat(16));
+    assert!(matches!(
+        inventory.write_item_data(&prototypes, 0, payload.clone(), Some("key")),
+        Err(ItemDataError::TooLarge { size, capacity: 16 }) if size == payload.size()
+    ));
+    assert_eq!(
+        inventory
+            .read_item_data(&prototypes, 0, Some("key"))
+            .unwrap(),
+        DataValue::Nil
+    );
+}
+
+#[test]
+fn items_without_data_storage_have_no_data() {
+    let prototypes = prototypes();
+    let mut inventory = inventory(2, None);
+    inventory.insert(&plate(), ItemStack::new("plate", 1));
+    assert!(matches!(
+        inventory.write_item_data(&prototypes, 0, DataValue::Integer(1), None),
+        Err(ItemDataError::NoDataStorage(item)) if item == "plate"
+    ));
+    assert!(matches!(
+        inventory.read_item_data(&prototypes, 1, None),
+        Err(ItemDataError::EmptySlot)
+    ));
+}
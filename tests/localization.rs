@@ -0,0 +1,107 @@
+//! Display names and descriptions of prototypes from per-language string tables.
+
+use scriplets::prototypes::{
+    Item, Problem, Prototypes, PrototypesError, PrototypesFragment, SourceLocation,
+};
+use std::path::Path;
+
+fn fragment(source: &str, file: &str) -> PrototypesFragment {
+    PrototypesFragment::from_bytes(source.as_bytes(), Path::new(file)).unwrap()
+}
+
+const ITEMS: &str = r#"{
+    "item": [
+        {
+            "name": "plate",
+            "stack_size": 10,
+            "display_name": "item-plate",
+            "description": "item-plate-description"
+        },
+        {"name": "gear", "stack_size": 10}
+    ]
+}"#;
+
+fn load(packs: &[Vec<PrototypesFragment>]) -> (Prototypes, Vec<Problem>) {
+    let packs: Vec<Vec<&PrototypesFragment>> =
+        packs.iter().map(|pack| pack.iter().collect()).collect();
+    Prototypes::from_packs(&packs).unwrap()
+}
+
+#[test]
+fn strings_fall_back_to_english_and_names() {
+    let (prototypes, _) = load(&[
+        vec![
+            fragment(ITEMS, "items.json"),
+            fragment(
+                r#"{"item-plate": "Plate", "item-plate-description": "Flat"}"#,
+                "locale/en.json",
+            ),
+            fragment(r#"{"item-plate": "Platte"}"#, "locale/de.json"),
+        ],
+        vec![fragment(
+            r#"{"item-plate": "Iron plate"}"#,
+            "locale/en.json",
+        )],
+    ]);
+    let localization = &prototypes.localization;
+    let plate = prototypes.get::<Item>("plate").unwrap();
+    let gear = prototypes.get::<Item>("gear").unwrap();
+    assert_eq!(localization.display_name(plate, "de"), "Platte");
+    // English strings of the later pack override the earlier ones
+    assert_eq!(localization.display_name(plate, "en"), "Iron plate");
+    assert_eq!(localization.display_name(plate, "fr"), "Iron plate");
+    assert_eq!(localization.description(plate, "de"), Some("Flat"));
+    assert_eq!(localization.display_name(gear, "de"), "gear");
+    assert_eq!(localization.description(gear, "de"), None);
+}
+
+#[test]
+fn missing_strings_are_reported_at_the_field() {
+    let (_, problems) = load(&[vec![
+        fragment(ITEMS, "items.json"),
+        fragment(r#"{"item-plate": "Plate"}"#, "locale/en.json"),
+        fragment(r#"{"item-plate-description": "Flach"}"#, "locale/de.json"),
+    ]]);
+    let problems: Vec<(String, SourceLocation)> = problems
+        .into_iter()
+        .map(|problem| {
+            assert!(!problem.is_error());
+            (problem.message, problem.location)
+        })
+        .collect();
+    let at = |line, column| SourceLocation {
+        file: "items.json".into(),
+        line,
+        column,
+    };
+    assert_eq!(
+        problems,
+        [
+            (
+                "`display_name` key `item-plate` has no string in `de`, English is used instead"
+                    .to_string(),
+                at(6, 29)
+            ),
+            (
+                "`description` key `item-plate-description` has no English string".to_string(),
+                at(7, 28)
+            ),
+        ]
+    );
+}
+
+#[test]
+fn strings_that_are_not_strings_are_located_by_their_key() {
+    let source = r#"{
+    "item-gear": "Unlike item-plate",
+    "item-plate": 5
+}"#;
+    let result = PrototypesFragment::from_bytes(source.as_bytes(), Path::new("locale/en.json"));
+    match result {
+        Err(PrototypesError::Syntax { location, message }) => {
+            assert_eq!((location.line, location.column), (3, 5));
+            assert_eq!(message, "`item-plate` has to be a string");
+        }
+        other => panic!("expected a syntax error, got {:?}", other.map(|_| ())),
+    }
+}
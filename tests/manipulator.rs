@@ -0,0 +1,179 @@
+//! Scanning for items in reach of manipulators and picking them up.
+
+use bevy::{asset::AssetPlugin, prelude::*};
+use scriplets::{
+    items::{ItemStack, WorldItem},
+    manipulator::{process_pickups, ManipulatorError},
+    prototypes::{
+        ComponentPrototype, Inventory, InventoryPrototype, Manipulator, ManipulatorPrototype,
+        PickupArea, Prototypes, PrototypesFragment,
+    },
+    PrototypesHandle, Unit,
+};
+use std::path::Path;
+
+fn manipulator() -> Manipulator {
+    ManipulatorPrototype {
+        name: "test".to_string(),
+        reach: 2.0,
+        area: PickupArea::Circle { radius: 1.0 },
+        speed: 2.0,
+    }
+    .to_component()
+}
+
+fn inventory() -> Inventory {
+    InventoryPrototype {
+        name: "test".to_string(),
+        slots: 1,
+        max_stack_size: None,
+        transfer_range: 1.0,
+    }
+    .to_component()
+}
+
+#[test]
+fn scan_finds_items_in_the_area() {
+    let mut manipulator = manipulator();
+    let plates = ItemStack::new("plate", 5);
+    let cards = ItemStack::new("card", 1);
+    let nearby = [
+        (Entity::from_raw(1), Vec2::new(2.5, 0.0), &plates),
+        (Entity::from_raw(2), Vec2::new(0.0, 2.5), &cards),
+    ];
+    let found = manipulator.scan(Vec2::new(2.0, 0.0), &nearby).unwrap();
+    assert_eq!(found, [(Entity::from_raw(1), &plates)]);
+    assert_eq!(manipulator.scanned, [Entity::from_raw(1)]);
+}
+
+#[test]
+fn areas_out_of_reach_cannot_be_scanned() {
+    let mut manipulator = manipulator();
+    assert!(matches!(
+        manipulator.scan(Vec2::new(3.0, 0.0), &[]),
+        Err(ManipulatorError::OutOfReach { distance, reach }) if distance == 3.0 && reach == 2.0
+    ));
+    assert_eq!(manipulator.max_distance(), 3.0);
+}
+
+#[test]
+fn only_scanned_items_can_be_picked_up() {
+    let mut manipulator = manipulator();
+    let plates = ItemStack::new("plate", 5);
+    let nearby = [(Entity::from_raw(1), Vec2::ZERO, &plates)];
+    manipulator.scan(Vec2::ZERO, &nearby).unwrap();
+    assert!(matches!(
+        manipulator.pick_up(Entity::from_raw(2)),
+        Err(ManipulatorError::NotScanned)
+    ));
+    manipulator.pick_up(Entity::from_raw(1)).unwrap();
+    assert!(matches!(
+        manipulator.pick_up(Entity::from_raw(1)),
+        Err(ManipulatorError::Busy)
+    ));
+}
+
+fn app() -> App {
+    let fragment = PrototypesFragment::from_json(
+        br#"{"item": [{"name": "plate", "stack_size": 10}]}"#,
+        Path::new("test.json"),
+    )
+    .unwrap();
+    let (prototypes, _) = Prototypes::from_packs(&[vec![&fragment]]).unwrap();
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin)
+        .add_asset::<Prototypes>()
+        .add_system(process_pickups);
+    let handle = app
+        .world
+        .resource_mut::<Assets<Prototypes>>()
+        .add(prototypes);
+    app.insert_resource(PrototypesHandle(handle));
+    app
+}
+
+fn spawn_item(app: &mut App, count: u32, position: Vec2) -> Entity {
+    app.world
+        .spawn()
+        .insert(WorldItem(ItemStack::new("plate", count)))
+        .insert(Transform::from_translation(position.extend(0.0)))
+        .id()
+}
+
+fn spawn_unit(app: &mut App, target: Entity) -> Entity {
+    let mut manipulator = manipulator();
+    manipulator.pending_pickup = Some(target);
+    app.world
+        .spawn()
+        .insert(Unit)
+        .insert(manipulator)
+        .insert(inventory())
+        .insert(Transform::default())
+        .id()
+}
+
+fn picked_up(app: &App, unit: Entity) -> u32 {
+    app.world.get::<Inventory>(unit).unwrap().count("plate")
+}
+
+#[test]
+fn picked_up_items_move_to_the_inventory() {
+    let mut app = app();
+    let item = spawn_item(&mut app, 15, Vec2::new(2.5, 0.0));
+    let unit = spawn_unit(&mut app, item);
+    app.update();
+    assert_eq!(picked_up(&app, unit), 10);
+    assert_eq!(app.world.get::<WorldItem>(item).unwrap().0.count, 5);
+    let manipulator = app.world.get::<Manipulator>(unit).unwrap();
+    assert!(manipulator.pending_pickup.is_none());
+    assert_eq!(manipulator.cooldown, 0.5);
+    assert!(matches!(manipulator.last_pickup, Some(Ok(10))));
+}
+
+#[test]
+fn item_picked_up_by_two_units_is_not_duplicated() {
+    let mut app = app();
+    let item = spawn_item(&mut app, 8, Vec2::ZERO);
+    let first = spawn_unit(&mut app, item);
+    let second = spawn_unit(&mut app, item);
+    app.update();
+    assert_eq!(picked_up(&app, first) + picked_up(&app, second), 8);
+    assert!(app.world.get_entity(item).is_none());
+}
+
+#[test]
+fn items_out_of_reach_since_the_scan_are_not_picked_up() {
+    let mut app = app();
+    let item = spawn_item(&mut app, 5, Vec2::ZERO);
+    let unit = spawn_unit(&mut app, item);
+    // The unit drove away after scanning
+    app.world.get_mut::<Transform>(unit).unwrap().translation = Vec3::new(3.5, 0.0, 0.0);
+    app.update();
+    assert_eq!(picked_up(&app, unit), 0);
+    assert_eq!(app.world.get::<WorldItem>(item).unwrap().0.count, 5);
+    let manipulator = app.world.get::<Manipulator>(unit).unwrap();
+    assert!(manipulator.pending_pickup.is_none());
+    assert_eq!(manipulator.cooldown, 0.0);
+    assert!(matches!(
+        manipulator.last_pickup,
+        Some(Err(ManipulatorError::ItemOutOfReach { distance, max_distance }))
+            if distance == 3.5 && max_distance == 3.0
+    ));
+}
+
+#[test]
+fn units_without_an_inventory_fail_to_pick_up() {
+    let mut app = app();
+    let item = spawn_item(&mut app, 5, Vec2::ZERO);
+    let unit = spawn_unit(&mut app, item);
+    app.world.entity_mut(unit).remove::<Inventory>();
+    app.update();
+    assert_eq!(app.world.get::<WorldItem>(item).unwrap().0.count, 5);
+    let manipulator = app.world.get::<Manipulator>(unit).unwrap();
+    assert!(manipulator.pending_pickup.is_none());
+    assert!(matches!(
+        manipulator.last_pickup,
+        Some(Err(ManipulatorError::NoInventory))
+    ));
+}
@@ -0,0 +1,164 @@
+//! Migration of prototype files written for older versions of the format. Each `<case>.<ext>` in
+//! `tests/fixtures/migrations` is merged as a single pack and compared with
+//! `<case>.expected.json`. Cases in `tests/fixtures/injected_migrations` are migrated with the
+//! migrations of this file instead of the engine's ones.
+
+use scriplets::prototypes::{
+    merge::{merge_packs, to_canonical_json},
+    migration::{
+        migrate_with, Migration, RawCategories, RawPrototype, FORMAT_VERSION, VERSION_KEY,
+    },
+    Item, Prototypes, PrototypesError, PrototypesFragment, SourceLocation,
+};
+use serde_json::{Map, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/migrations");
+const INJECTED_FIXTURES: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/injected_migrations"
+);
+const EXPECTED_SUFFIX: &str = ".expected.json";
+
+/// Version 2 renamed `stack` of items to `stack_size`.
+fn rename_stack(category: &mut String, fields: &mut Map<String, Value>) {
+    if category == "items" {
+        if let Some(stack) = fields.remove("stack") {
+            fields.insert("stack_size".to_string(), stack);
+        }
+    }
+}
+
+/// Version 3 moved items from `items` to `item`.
+fn move_items(category: &mut String, _fields: &mut Map<String, Value>) {
+    if category == "items" {
+        *category = "item".to_string();
+    }
+}
+
+const INJECTED_MIGRATIONS: [Migration; 2] = [rename_stack, move_items];
+
+fn cases(fixtures: &str) -> Vec<PathBuf> {
+    let mut cases: Vec<PathBuf> = fs::read_dir(fixtures)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| !path.to_string_lossy().ends_with(EXPECTED_SUFFIX))
+        .collect();
+    cases.sort();
+    cases
+}
+
+fn load(path: &Path) -> PrototypesFragment {
+    let bytes = fs::read(path).unwrap();
+    PrototypesFragment::from_bytes(&bytes, path)
+        .unwrap_or_else(|e| panic!("{} failed to load: {}", path.display(), e))
+}
+
+fn expected(case: &Path) -> Value {
+    let stem = case.file_stem().unwrap().to_str().unwrap();
+    let expected = case.with_file_name(format!("{}{}", stem, EXPECTED_SUFFIX));
+    serde_json::from_slice(&fs::read(expected).unwrap()).unwrap()
+}
+
+#[test]
+fn fixtures_migrate_to_expected() {
+    let cases = cases(FIXTURES);
+    assert!(!cases.is_empty(), "no fixtures in {}", FIXTURES);
+    for case in cases {
+        let fragment = load(&case);
+        let (merged, problems) = merge_packs(&[vec![&fragment]]);
+        assert!(problems.is_empty(), "{}: {:?}", case.display(), problems);
+        assert_eq!(
+            to_canonical_json(&merged),
+            expected(&case),
+            "{}",
+            case.display()
+        );
+    }
+}
+
+/// Version and prototypes of a JSON fixture, as files are before they are migrated.
+fn load_raw(path: &Path) -> (u32, RawCategories) {
+    let mut file: Map<String, Value> = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+    let version = file.remove(VERSION_KEY).unwrap().as_u64().unwrap() as u32;
+    let location = SourceLocation {
+        file: path.to_path_buf(),
+        line: 1,
+        column: 1,
+    };
+    let categories = file
+        .into_iter()
+        .map(|(category, prototypes)| {
+            let prototypes = serde_json::from_value::<Vec<Map<String, Value>>>(prototypes)
+                .unwrap()
+                .into_iter()
+                .map(|fields| RawPrototype {
+                    location: location.clone(),
+                    field_locations: Default::default(),
+                    fields,
+                })
+                .collect();
+            (category, prototypes)
+        })
+        .collect();
+    (version, categories)
+}
+
+#[test]
+fn injected_migrations_run_from_the_file_version() {
+    let cases = cases(INJECTED_FIXTURES);
+    assert!(!cases.is_empty(), "no fixtures in {}", INJECTED_FIXTURES);
+    for case in cases {
+        let (version, categories) = load_raw(&case);
+        let migrated: Map<String, Value> = migrate_with(&INJECTED_MIGRATIONS, version, categories)
+            .into_iter()
+            .map(|(category, prototypes)| {
+                let prototypes = prototypes
+                    .into_iter()
+                    .map(|prototype| Value::Object(prototype.fields))
+                    .collect();
+                (category, Value::Array(prototypes))
+            })
+            .collect();
+        assert_eq!(
+            Value::Object(migrated),
+            expected(&case),
+            "{}",
+            case.display()
+        );
+    }
+}
+
+#[test]
+fn unversioned_prototypes_load() {
+    let fragment = load(&Path::new(FIXTURES).join("unversioned.ron"));
+    let (prototypes, _) = Prototypes::from_packs(&[vec![&fragment]]).unwrap();
+    let item = prototypes.get::<Item>("copper-plate").unwrap();
+    assert_eq!(item.stack_size, 50);
+}
+
+#[test]
+fn newer_version_is_rejected() {
+    let bytes = format!("{{\"format_version\": {}}}", FORMAT_VERSION + 1);
+    let result = PrototypesFragment::from_bytes(bytes.as_bytes(), Path::new("newer.json"));
+    assert!(matches!(
+        result,
+        Err(PrototypesError::UnsupportedVersion { version, .. }) if version == FORMAT_VERSION as u64 + 1
+    ));
+}
+
+#[test]
+fn invalid_version_is_rejected() {
+    for version in ["0", "\"2\"", "1.5"] {
+        let bytes = format!("{{\"format_version\": {}}}", version);
+        let result = PrototypesFragment::from_bytes(bytes.as_bytes(), Path::new("invalid.json"));
+        assert!(
+            matches!(result, Err(PrototypesError::Syntax { .. })),
+            "version {}",
+            version
+        );
+    }
+}
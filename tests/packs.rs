@@ -0,0 +1,128 @@
+//! Merging prototypes of several packs in order and resolving inheritance between them.
+
+use scriplets::prototypes::{
+    InventoryPrototype, Item, Prototypes, PrototypesError, PrototypesFragment,
+};
+use std::path::Path;
+
+fn fragment(source: &str, file: &str) -> PrototypesFragment {
+    PrototypesFragment::from_bytes(source.as_bytes(), Path::new(file)).unwrap()
+}
+
+fn load(packs: &[Vec<PrototypesFragment>]) -> Prototypes {
+    let packs: Vec<Vec<&PrototypesFragment>> =
+        packs.iter().map(|pack| pack.iter().collect()).collect();
+    let (prototypes, problems) = Prototypes::from_packs(&packs).unwrap();
+    assert!(problems.is_empty(), "{:?}", problems);
+    prototypes
+}
+
+const BASE: &str = r#"{
+    "item": [
+        {"name": "plate", "stack_size": 10},
+        {"name": "gear", "stack_size": 20}
+    ]
+}"#;
+
+#[test]
+fn later_packs_override_prototypes() {
+    let prototypes = load(&[
+        vec![fragment(BASE, "base/items.json")],
+        vec![fragment(
+            r#"{"item": [{"name": "plate", "stack_size": 50}]}"#,
+            "mod/items.json",
+        )],
+    ]);
+    assert_eq!(prototypes.get::<Item>("plate").unwrap().stack_size, 50);
+    assert_eq!(prototypes.get::<Item>("gear").unwrap().stack_size, 20);
+}
+
+#[test]
+fn later_packs_remove_prototypes() {
+    let prototypes = load(&[
+        vec![fragment(BASE, "base/items.json")],
+        vec![fragment(
+            r#"{"item": [{"name": "gear", "remove": true}]}"#,
+            "mod/items.json",
+        )],
+    ]);
+    assert!(prototypes.get::<Item>("gear").is_none());
+    assert!(prototypes.get::<Item>("plate").is_some());
+}
+
+#[test]
+fn removed_prototypes_can_be_defined_again_by_a_later_pack() {
+    let prototypes = load(&[
+        vec![fragment(BASE, "base/items.json")],
+        vec![fragment(
+            r#"{"item": [{"name": "gear", "remove": true}]}"#,
+            "mod/items.json",
+        )],
+        vec![fragment(
+            r#"{"item": [{"name": "gear", "stack_size": 5}]}"#,
+            "other/items.json",
+        )],
+    ]);
+    assert_eq!(prototypes.get::<Item>("gear").unwrap().stack_size, 5);
+}
+
+#[test]
+fn children_inherit_fields_they_do_not_set() {
+    let source = r#"{
+    "inventory": [
+        {"name": "base", "abstract": true, "slots": 4, "transfer_range": 2},
+        {"name": "small", "parent": "base"},
+        {"name": "large", "parent": "small", "slots": 16},
+        {"name": "larger", "parent": "large", "max_stack_size": 5}
+    ]
+}"#;
+    let prototypes = load(&[vec![fragment(source, "inventories.json")]]);
+    let inventory = |name| prototypes.get::<InventoryPrototype>(name);
+    assert!(inventory("base").is_none());
+    assert_eq!(inventory("small").unwrap().slots, 4);
+    assert_eq!(inventory("large").unwrap().slots, 16);
+    let larger = inventory("larger").unwrap();
+    assert_eq!(larger.slots, 16);
+    assert_eq!(larger.transfer_range, 2.0);
+    assert_eq!(larger.max_stack_size, Some(5));
+}
+
+fn inheritance_errors(source: &str) -> Vec<String> {
+    let fragment = fragment(source, "inventories.json");
+    match Prototypes::from_packs(&[vec![&fragment]]) {
+        Err(PrototypesError::Invalid(problems)) => problems
+            .into_iter()
+            .map(|problem| {
+                assert!(problem.is_error());
+                format!("{}: {}", problem.name.unwrap(), problem.message)
+            })
+            .collect(),
+        other => panic!("expected invalid prototypes, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn prototypes_inheriting_from_themselves_are_rejected() {
+    let source = r#"{"inventory": [{"name": "loop", "parent": "loop", "slots": 1}]}"#;
+    assert_eq!(
+        inheritance_errors(source),
+        ["loop: inheritance cycle: loop -> loop"]
+    );
+}
+
+#[test]
+fn inheritance_cycles_are_rejected() {
+    let source = r#"{
+    "inventory": [
+        {"name": "a", "parent": "b", "slots": 1},
+        {"name": "b", "parent": "a", "slots": 1}
+    ]
+}"#;
+    assert_eq!(
+        inheritance_errors(source),
+        [
+            "a: inheritance cycle: a -> b -> a",
+            "b: inheritance cycle: b -> a -> b"
+        ]
+    );
+}
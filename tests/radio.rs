@@ -0,0 +1,93 @@
+//! Sending, receiving and bandwidth of radios.
+
+use bevy::prelude::*;
+use scriplets::{
+    data_value::DataValue,
+    prototypes::{ComponentPrototype, Radio, RadioPrototype},
+    radio::{RadioError, RadioMessage},
+};
+use std::time::Duration;
+
+fn radio() -> Radio {
+    RadioPrototype {
+        name: "test".to_string(),
+        range: 10.0,
+        bandwidth: 100.0,
+        max_message_size: 60,
+        mailbox_size: 2,
+    }
+    .to_component()
+}
+
+/// Value taking exactly `size` bytes.
+fn data(size: usize) -> DataValue {
+    DataValue::String("x".repeat(size - 2))
+}
+
+fn message(channel: u32) -> RadioMessage {
+    RadioMessage {
+        sender: Entity::from_raw(1),
+        channel,
+        data: DataValue::Nil,
+    }
+}
+
+#[test]
+fn fresh_radio_can_send() {
+    let mut radio = radio();
+    assert_eq!(radio.bandwidth_available(), 100.0);
+    radio.send(Entity::from_raw(0), 1, data(50)).unwrap();
+    assert_eq!(radio.outbox.len(), 1);
+    assert_eq!(radio.bandwidth_available(), 50.0);
+}
+
+#[test]
+fn messages_over_the_maximum_size_are_rejected() {
+    let mut radio = radio();
+    assert!(matches!(
+        radio.send(Entity::from_raw(0), 1, data(61)),
+        Err(RadioError::MessageTooLarge { size: 61, max: 60 })
+    ));
+    assert!(radio.outbox.is_empty());
+    assert_eq!(radio.bandwidth_available(), 100.0);
+}
+
+#[test]
+fn sending_uses_up_bandwidth() {
+    let mut radio = radio();
+    radio.send(Entity::from_raw(0), 1, data(60)).unwrap();
+    assert!(matches!(
+        radio.send(Entity::from_raw(0), 1, data(50)),
+        Err(RadioError::NotEnoughBandwidth {
+            size: 50,
+            available: 40
+        })
+    ));
+    radio.send(Entity::from_raw(0), 1, data(40)).unwrap();
+    assert_eq!(radio.outbox.len(), 2);
+    assert_eq!(radio.bandwidth_available(), 0.0);
+}
+
+#[test]
+fn bandwidth_refills_up_to_one_second_worth() {
+    let mut radio = radio();
+    radio.send(Entity::from_raw(0), 1, data(60)).unwrap();
+    radio.refill_bandwidth(Duration::from_millis(250));
+    assert_eq!(radio.bandwidth_available(), 65.0);
+    radio.refill_bandwidth(Duration::from_secs(10));
+    assert_eq!(radio.bandwidth_available(), 100.0);
+}
+
+#[test]
+fn messages_over_the_mailbox_size_are_dropped() {
+    let mut radio = radio();
+    assert!(radio.receive(message(1)));
+    assert!(radio.receive(message(2)));
+    assert!(!radio.receive(message(3)));
+    let channels: Vec<u32> = radio
+        .mailbox
+        .iter()
+        .map(|message| message.channel)
+        .collect();
+    assert_eq!(channels, [1, 2]);
+}
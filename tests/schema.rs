@@ -0,0 +1,57 @@
+//! JSON Schema of prototype files, generated from the prototype types.
+
+use scriplets::prototypes::schema::prototypes_schema;
+use serde_json::{json, Value};
+
+fn schema() -> Value {
+    serde_json::to_value(prototypes_schema()).unwrap()
+}
+
+fn prototype_properties<'a>(schema: &'a Value, category: &str) -> &'a Value {
+    &schema["properties"][category]["items"]["properties"]
+}
+
+#[test]
+fn enum_variants_are_kebab_case() {
+    let schema = schema();
+    let movement = prototype_properties(&schema, "movement");
+    assert_eq!(
+        movement["movement_type"],
+        json!({"$ref": "#/definitions/MovementType"})
+    );
+    assert_eq!(
+        schema["definitions"]["MovementType"]["enum"],
+        json!(["omnidirectional", "accelerated-steering", "train"])
+    );
+}
+
+#[test]
+fn serde_defaults_are_in_the_schema() {
+    let schema = schema();
+    let movement = prototype_properties(&schema, "movement");
+    assert_eq!(movement["speed"]["default"], json!(0.0));
+    assert_eq!(movement["max_speed_backwards"]["default"], Value::Null);
+    assert!(movement["max_speed_backwards"]
+        .as_object()
+        .unwrap()
+        .contains_key("default"));
+    // Fields without a default have none in the schema
+    assert!(movement["movement_type"].get("default").is_none());
+}
+
+#[test]
+fn only_names_are_required() {
+    let schema = schema();
+    for category in ["item", "movement", "unit"] {
+        assert_eq!(
+            schema["properties"][category]["items"]["required"],
+            json!(["name"]),
+            "{}",
+            category
+        );
+    }
+    assert_eq!(
+        prototype_properties(&schema, "item")["parent"]["type"],
+        "string"
+    );
+}
@@ -0,0 +1,72 @@
+//! Spawning units with the parts their prototypes declare.
+
+use bevy::{asset::AssetPlugin, ecs::system::CommandQueue, prelude::*};
+use scriplets::{
+    program::UnitProgram,
+    prototypes::{Inventory, Manipulator, Movement, Prototypes, PrototypesFragment, Radio},
+    unit::{spawn_unit, Health, SpawnUnitError},
+    Unit,
+};
+use std::path::Path;
+
+const PROTOTYPES: &str = r#"{
+    "movement": [{"name": "wheels", "movement_type": "omnidirectional", "speed": 1}],
+    "inventory": [{"name": "small", "slots": 4}],
+    "unit": [
+        {
+            "name": "scout",
+            "collider": {"shape": "circle", "radius": 0.5},
+            "sprite": "scout.png",
+            "health": 10,
+            "movement": "wheels",
+            "inventory": "small"
+        }
+    ]
+}"#;
+
+/// World with an asset server, and the unit spawned in it.
+fn spawn(name: &str) -> (World, Result<Entity, SpawnUnitError>) {
+    let fragment =
+        PrototypesFragment::from_json(PROTOTYPES.as_bytes(), Path::new("units.json")).unwrap();
+    let (prototypes, _) = Prototypes::from_packs(&[vec![&fragment]]).unwrap();
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins).add_plugin(AssetPlugin);
+    let mut world = std::mem::take(&mut app.world);
+    let mut queue = CommandQueue::default();
+    let asset_server = world.resource::<AssetServer>();
+    let mut commands = Commands::new(&mut queue, &world);
+    let result = spawn_unit(
+        &mut commands,
+        &prototypes,
+        asset_server,
+        name,
+        Transform::from_xyz(1.0, 2.0, 0.0),
+        b"function on_tick(handle) end",
+    );
+    queue.apply(&mut world);
+    (world, result)
+}
+
+#[test]
+fn units_get_the_parts_of_their_prototype() {
+    let (world, unit) = spawn("scout");
+    let unit = world.entity(unit.unwrap());
+    assert!(unit.contains::<Unit>());
+    assert!(unit.contains::<UnitProgram>());
+    assert_eq!(unit.get::<Health>().unwrap().0, 10.0);
+    assert_eq!(unit.get::<Movement>().unwrap().name, "wheels");
+    assert_eq!(unit.get::<Inventory>().unwrap().slots, 4);
+    assert!(!unit.contains::<Radio>());
+    assert!(!unit.contains::<Manipulator>());
+    assert_eq!(
+        unit.get::<Transform>().unwrap().translation,
+        Vec3::new(1.0, 2.0, 0.0)
+    );
+}
+
+#[test]
+fn unknown_units_are_not_spawned() {
+    let (world, unit) = spawn("tank");
+    assert!(matches!(unit, Err(SpawnUnitError::UnknownUnit(name)) if name == "tank"));
+    assert_eq!(world.entities().len(), 0);
+}
@@ -0,0 +1,155 @@
+//! Problems found in merged prototypes, reported with the location of the prototype or the field
+//! that caused them.
+
+use scriplets::prototypes::{
+    Item, Problem, Prototypes, PrototypesError, PrototypesFragment, SourceLocation,
+};
+use std::path::Path;
+
+fn location(line: usize, column: usize, file: &str) -> SourceLocation {
+    SourceLocation {
+        file: file.into(),
+        line,
+        column,
+    }
+}
+
+fn fragment(source: &str, file: &str) -> PrototypesFragment {
+    PrototypesFragment::from_bytes(source.as_bytes(), Path::new(file)).unwrap()
+}
+
+fn problems(fragments: &[PrototypesFragment]) -> Vec<Problem> {
+    match Prototypes::from_packs(&[fragments.iter().collect()]) {
+        Ok((_, warnings)) => warnings,
+        Err(PrototypesError::Invalid(problems)) => problems,
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+}
+
+const INVENTORY: &str = r#"{"inventory": [{"name": "small", "slots": 4}]}"#;
+
+#[test]
+fn dangling_references_are_reported_at_the_field() {
+    let units = r#"{
+    "unit": [
+        {
+            "name": "scout",
+            "collider": {"shape": "circle", "radius": 0.5},
+            "sprite": "scout.png",
+            "health": 10,
+            "inventory": "large"
+        }
+    ]
+}"#;
+    let problems = problems(&[
+        fragment(INVENTORY, "inventory.json"),
+        fragment(units, "units.json"),
+    ]);
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+    assert!(problems[0].is_error());
+    assert_eq!(problems[0].location, location(8, 26, "units.json"));
+    assert_eq!(
+        problems[0].message,
+        "`inventory` references inventory prototype `large`, which doesn't exist"
+    );
+}
+
+#[test]
+fn dangling_references_in_other_formats_are_reported_at_the_key() {
+    let black_boxes = r#"[[black_box]]
+name = "recorder"
+capacity = 64
+item = "tape"
+"#;
+    let problems = problems(&[fragment(black_boxes, "black_boxes.toml")]);
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+    assert_eq!(problems[0].location, location(4, 1, "black_boxes.toml"));
+    assert_eq!(
+        problems[0].message,
+        "`item` references item prototype `tape`, which doesn't exist"
+    );
+}
+
+#[test]
+fn existing_references_are_accepted() {
+    let units = r#"{
+    "unit": [
+        {
+            "name": "scout",
+            "collider": {"shape": "circle", "radius": 0.5},
+            "sprite": "scout.png",
+            "health": 10,
+            "inventory": "small"
+        }
+    ]
+}"#;
+    let problems = problems(&[
+        fragment(INVENTORY, "inventory.json"),
+        fragment(units, "units.json"),
+    ]);
+    assert!(problems.is_empty(), "{:?}", problems);
+}
+
+#[test]
+fn prototypes_defined_twice_in_a_pack_are_reported_with_both_locations() {
+    let first = r#"{"item": [{"name": "plate", "stack_size": 10}]}"#;
+    let second = r#"{
+    "item": [
+        {"name": "plate", "stack_size": 20}
+    ]
+}"#;
+    let problems = problems(&[fragment(second, "b.json"), fragment(first, "a.json")]);
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+    assert!(problems[0].is_error());
+    assert_eq!(problems[0].location, location(3, 9, "b.json"));
+    assert_eq!(
+        problems[0].message,
+        "defined more than once, first definition is at a.json:1:11"
+    );
+}
+
+#[test]
+fn invalid_values_are_reported_at_the_field() {
+    let source = r#"{
+    "inventory": [
+        {
+            "name": "broken",
+            "slots": 0,
+            "transfer_range": 1
+        }
+    ],
+    "movement": [
+        {
+            "name": "wheels",
+            "movement_type": "omnidirectional",
+            "speed": 1,
+            "acceleration": 2
+        }
+    ]
+}"#;
+    let problems = problems(&[fragment(source, "parts.json")]);
+    let problems: Vec<String> = problems.iter().map(Problem::to_string).collect();
+    assert_eq!(
+        problems,
+        [
+            "error: parts.json:5:22: inventory prototype `broken`: `slots` has to be greater than 0",
+            "warning: parts.json:14:29: movement prototype `wheels`: `acceleration` has no effect on omnidirectional movement",
+        ]
+    );
+}
+
+#[test]
+fn unknown_categories_are_ignored_with_a_warning() {
+    let source = r#"{
+    "item": [{"name": "plate", "stack_size": 10}],
+    "gadget": [{"name": "widget"}]
+}"#;
+    let fragment = fragment(source, "mod.json");
+    let (prototypes, warnings) = Prototypes::from_packs(&[vec![&fragment]]).unwrap();
+    let warnings: Vec<String> = warnings.iter().map(Problem::to_string).collect();
+    assert_eq!(
+        warnings,
+        ["warning: mod.json:3:16: gadget prototype `widget`: unknown category, the prototype is ignored"]
+    );
+    assert!(prototypes.get::<Item>("plate").is_some());
+}